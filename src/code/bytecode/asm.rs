@@ -0,0 +1,236 @@
+use super::*;
+use crate::memory::dump::parse_address;
+
+use std::fmt::Write;
+
+// format, one line per instruction, following a line for the activation:
+//
+//     activation 1101
+//        0  read -0x1        ; depth 1  <1
+//        1  push input       ; depth 2  I
+//        2  xor              ; depth 1  ^
+//
+// - the activation pattern's bits as `0` and `1`, in ascending address order
+// - each instruction's index, then its mnemonic, which is one of:
+//   - `push` followed by `no`, `yes`, `input`, `output`, `not input`,
+//     `not output`, or otherwise the truth table as a hex byte, of which only
+//     the low 4 bits may be set
+//   - `read` followed by the offset, as hex with an explicit sign
+//   - `and`, `or`, `xor`, `not`, `xnor` or `nor`
+// - a comment with the depth of the stack after the instruction, or `?` once
+//   it has underflowed, then the instruction's source, if known
+//
+// when assembling, the index is optional, offsets may be decimal, comments
+// starting with `;` are ignored, as are blank lines. the activation line may
+// be omitted if the pattern is empty.
+
+/// Error assembling a listing.
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub message: String,
+    /// Line number, starting at 1.
+    pub line: usize,
+}
+
+/// Named truth tables of push instructions, as their bitfields.
+const PUSH_NAMES: [(u8, &str); 6] = [
+    (0x00, "no"),
+    (0x0F, "yes"),
+    (0x0C, "input"),
+    (0x0A, "output"),
+    (0x03, "not input"),
+    (0x05, "not output"),
+];
+
+/// Mnemonics of operations.
+const OP_NAMES: [(OpInstr, &str); 6] = [
+    (OpInstr::Both, "and"),
+    (OpInstr::Either, "or"),
+    (OpInstr::Different, "xor"),
+    (OpInstr::Not, "not"),
+    (OpInstr::Same, "xnor"),
+    (OpInstr::Neither, "nor"),
+];
+
+/// Render a program as a listing, showing the source of each instruction if
+/// given the source code it was compiled from.
+pub fn disassemble(program: &CompiledProgram, code: Option<&str>) -> String {
+    let mut text = String::new();
+    text.push_str("activation");
+    if !program.activation.is_empty() {
+        text.push(' ');
+    }
+    text.extend(program.activation.iter().map(|&bit| if bit { '1' } else { '0' }));
+    text.push('\n');
+
+    let mut depth: Option<usize> = Some(0);
+    for (index, instr) in program.instrs.iter().enumerate() {
+        if let Instr::Operation(op) = *instr {
+            depth = depth.and_then(|depth| depth.checked_sub(op.arity()));
+        }
+        depth = depth.map(|depth| depth + 1);
+
+        let depth = depth.map(|depth| depth.to_string()).unwrap_or_else(|| "?".to_owned());
+        write!(text, "{:>4}  {:<16} ; depth {}", index, mnemonic(instr), depth).unwrap();
+
+        let source = code.and_then(|code| program.instr_source(index)?.text(code));
+        if let Some(source) = source {
+            write!(text, "  {}", source.split_whitespace().collect::<Vec<_>>().join(" ")).unwrap();
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// Parse a listing into a program.
+///
+/// Listings hold no source locations, so the program has no debug info.
+pub fn assemble(text: &str) -> Result<CompiledProgram, ParseError> {
+    let mut activation: Option<Vec<bool>> = None;
+    let mut instrs: Vec<Instr> = Vec::new();
+
+    for (line_index, line) in text.lines().enumerate() {
+        let error = |message: String| ParseError {
+            message,
+            line: line_index + 1,
+        };
+
+        let line = line.split(';').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut words: Vec<&str> = line.split_whitespace().collect();
+        if words[0] == "activation" {
+            if activation.is_some() || !instrs.is_empty() {
+                return Err(error("activation must come first, and only once".to_owned()));
+            }
+            let bits = words.get(1).copied().unwrap_or("");
+            if words.len() > 2 || !bits.chars().all(|c| c == '0' || c == '1') {
+                return Err(error(format!("invalid activation `{}`", &line["activation".len()..].trim())));
+            }
+            activation = Some(bits.chars().map(|c| c == '1').collect());
+            continue;
+        }
+
+        // the index, if given, must match
+        if words[0].chars().all(|c| c.is_ascii_digit()) {
+            if words[0].parse() != Ok(instrs.len()) {
+                return Err(error(format!("expected instruction {}, found `{}`", instrs.len(), words[0])));
+            }
+            words.remove(0);
+        }
+
+        let instr = parse_instr(&words)
+            .ok_or_else(|| error(format!("invalid instruction `{}`", words.join(" "))))?;
+        instrs.push(instr);
+    }
+
+    Ok(CompiledProgram::new(activation.unwrap_or_default(), instrs, None))
+}
+
+fn mnemonic(instr: &Instr) -> String {
+    match *instr {
+        Instr::Value(PushInstr::Push(IoTruthTable(table))) => {
+            match PUSH_NAMES.iter().find(|&&(bitfield, _)| bitfield == table) {
+                Some((_, name)) => format!("push {}", name),
+                None => format!("push {:#04x}", table),
+            }
+        }
+        Instr::Value(PushInstr::ReadThenPush { offset }) => {
+            let sign = if offset < 0 { '-' } else { '+' };
+            format!("read {}{:#x}", sign, offset.unsigned_abs())
+        }
+        Instr::Operation(op) => {
+            let (_, name) = OP_NAMES.iter().find(|&&(o, _)| o == op).unwrap();
+            (*name).to_owned()
+        }
+    }
+}
+
+fn parse_instr(words: &[&str]) -> Option<Instr> {
+    match words {
+        ["push", operand @ ..] => {
+            let operand = operand.join(" ");
+            let table = match PUSH_NAMES.iter().find(|&&(_, name)| name == operand) {
+                Some(&(bitfield, _)) => bitfield,
+                None => {
+                    let hex = operand.strip_prefix("0x")?;
+                    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                        return None;
+                    }
+                    u8::from_str_radix(hex, 16).ok()
+                        .filter(|&table| table <= 0x0F)?
+                }
+            };
+            Some(Instr::Value(PushInstr::Push(IoTruthTable(table))))
+        }
+        ["read", offset] => {
+            Some(Instr::Value(PushInstr::ReadThenPush { offset: parse_address(offset)? }))
+        }
+        [name] => {
+            OP_NAMES.iter()
+                .find(|&&(_, n)| n == *name)
+                .map(|&(op, _)| Instr::Operation(op))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::compile::compile;
+
+    #[test]
+    fn round_trips() {
+        for code in &["1: <1", "1101: & | <1 ~ >3 ^ <fffffffff I", "0: ^ O ~ I", "1: | & <1 >1 ~ ^ <2 >2"] {
+            let program = compile(code).unwrap();
+            let stripped = CompiledProgram {
+                debug_info: None,
+                ..program.clone()
+            };
+            assert_eq!(assemble(&disassemble(&program, Some(code))).unwrap(), stripped);
+            assert_eq!(assemble(&disassemble(&program, None)).unwrap(), stripped);
+        }
+    }
+
+    #[test]
+    fn disassembles_golden() {
+        let code = "1: ^ <1 I";
+        let listing = "\
+activation 1
+   0  push input       ; depth 1  I
+   1  read -0x1        ; depth 2  <1
+   2  xor              ; depth 1  ^
+";
+        assert_eq!(disassemble(&compile(code).unwrap(), Some(code)), listing);
+    }
+
+    #[test]
+    fn assembles_loose_listings() {
+        // `not input` is a push, not an operation
+        let error = assemble("; comment\n\nread -10\n1 push 0x06 ; depth 2\nnot input\n").unwrap_err();
+        assert_eq!(error.line, 5);
+
+        let program = assemble("read -10\n  push 0x06\n2 xnor\n").unwrap();
+        assert!(program.activation.is_empty());
+        assert_eq!(program.instrs, vec![
+            Instr::Value(PushInstr::ReadThenPush { offset: -10 }),
+            Instr::Value(PushInstr::Push(IoTruthTable(0x06))),
+            Instr::Operation(OpInstr::Same),
+        ]);
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        let line = |text| assemble(text).unwrap_err().line;
+        assert_eq!(line("read 1\nactivation 1"), 2);
+        assert_eq!(line("activation 12"), 1);
+        assert_eq!(line("read 1\n0 not"), 2);
+        assert_eq!(line("read +-1"), 1);
+        assert_eq!(line("push 0x10"), 1);
+        assert_eq!(line("push maybe"), 1);
+        assert_eq!(line("frobnicate"), 1);
+    }
+}
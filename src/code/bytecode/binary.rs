@@ -0,0 +1,350 @@
+use super::*;
+
+use std::io::{self, Read, Write};
+
+/// Leading bytes of every bytecode file.
+pub const MAGIC: [u8; 4] = *b"BPBC";
+
+/// Version of the format written, and the only version read.
+pub const VERSION: u32 = 1;
+
+// format, with fixed-size integers little-endian, and varints as LEB128:
+//
+// - magic
+// - version, as u32
+// - activation pattern:
+//   - number of bits, as varint
+//   - bits, as bytes holding 8 bits each, least significant bit first, with
+//     any unused bits of the last byte zero
+// - number of instructions, as varint
+// - for each instruction, its tag as varint, followed by:
+//   - for push, the truth table, as a byte with its high 4 bits zero
+//   - for read, the offset, zig-zag encoded as varint
+//   - for operations, nothing
+// - whether debug info follows, as a byte 0 or 1
+// - if so, for each instruction:
+//   - 0 if its source is unknown, otherwise its start offset plus 1, as varint
+//   - if known, the length of its source, as varint
+
+/// Tag of a push instruction.
+const TAG_PUSH: u128 = 0;
+
+/// Tag of a read instruction.
+const TAG_READ: u128 = 1;
+
+/// Operations in the order of their tags, which follow the other tags.
+const OPS: [OpInstr; 6] = [
+    OpInstr::Both,
+    OpInstr::Either,
+    OpInstr::Different,
+    OpInstr::Not,
+    OpInstr::Same,
+    OpInstr::Neither,
+];
+
+/// Error reading a bytecode file.
+#[derive(Debug, Clone)]
+pub struct Error {
+    pub message: String,
+    pub kind: ErrorKind,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum ErrorKind {
+    Io,
+    BadMagic,
+    UnsupportedVersion,
+    InvalidVarint,
+    InvalidActivation,
+    InvalidInstr,
+    InvalidDebugInfo,
+    /// The encoding is valid, but the instructions do not evaluate to
+    /// exactly one value.
+    Malformed,
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error {
+            message: e.to_string(),
+            kind: ErrorKind::Io,
+        }
+    }
+}
+
+/// Write a program as bytecode, including its debug info if present.
+pub fn write_program<W: Write>(program: &CompiledProgram, mut writer: W) -> io::Result<()> {
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+
+    write_varint(&mut writer, program.activation.len() as u128)?;
+    let bytes: Vec<u8> = program.activation.chunks(8)
+        .map(|byte| byte.iter()
+            .enumerate()
+            .fold(0x00_u8, |value, (i, &bit)| value | ((bit as u8) << i)))
+        .collect();
+    writer.write_all(&bytes)?;
+
+    write_varint(&mut writer, program.instrs.len() as u128)?;
+    for instr in &program.instrs {
+        match *instr {
+            Instr::Value(PushInstr::Push(IoTruthTable(table))) => {
+                write_varint(&mut writer, TAG_PUSH)?;
+                writer.write_all(&[table])?;
+            }
+            Instr::Value(PushInstr::ReadThenPush { offset }) => {
+                write_varint(&mut writer, TAG_READ)?;
+                write_varint(&mut writer, zig_zag(offset))?;
+            }
+            Instr::Operation(op) => {
+                let index = OPS.iter().position(|&o| o == op).unwrap();
+                write_varint(&mut writer, TAG_READ + 1 + index as u128)?;
+            }
+        }
+    }
+
+    match &program.debug_info {
+        Some(debug_info) => {
+            writer.write_all(&[0x01])?;
+            for i in 0..program.instrs.len() {
+                match debug_info.instr_source(i) {
+                    Some(source) => {
+                        write_varint(&mut writer, source.start as u128 + 1)?;
+                        write_varint(&mut writer, source.end.saturating_sub(source.start) as u128)?;
+                    }
+                    None => write_varint(&mut writer, 0)?,
+                }
+            }
+        }
+        None => writer.write_all(&[0x00])?,
+    }
+    Ok(())
+}
+
+/// Read a program from bytecode, validating its encoding, and verifying its
+/// instructions with `verify::verify_program`, so that they can be run.
+///
+/// Reads exactly the bytes of the program, so further data may follow it.
+pub fn read_program<R: Read>(mut reader: R) -> Result<CompiledProgram, Error> {
+    let mut magic = [0x00; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(Error {
+            message: "not a bytecode file".to_owned(),
+            kind: ErrorKind::BadMagic,
+        });
+    }
+
+    let mut version = [0x00; 4];
+    reader.read_exact(&mut version)?;
+    let version = u32::from_le_bytes(version);
+    if version != VERSION {
+        return Err(Error {
+            message: format!("unsupported bytecode version {}", version),
+            kind: ErrorKind::UnsupportedVersion,
+        });
+    }
+
+    // activation pattern
+    let bit_count = read_len(&mut reader)?;
+    let byte_count = bit_count.div_ceil(8);
+    // read as the bytes arrive, rather than trusting the length
+    let mut bytes = Vec::new();
+    (&mut reader).take(byte_count as u64).read_to_end(&mut bytes)?;
+    if bytes.len() < byte_count {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    if !bit_count.is_multiple_of(8) && bytes[byte_count - 1] >> (bit_count % 8) != 0x00 {
+        return Err(Error {
+            message: "activation pattern has bits beyond its length".to_owned(),
+            kind: ErrorKind::InvalidActivation,
+        });
+    }
+    let activation: Vec<bool> = (0..bit_count)
+        .map(|i| bytes[i / 8] & (0x1 << (i % 8)) != 0)
+        .collect();
+
+    // instructions
+    let instr_count = read_len(&mut reader)?;
+    let mut instrs = Vec::new();
+    for index in 0..instr_count {
+        let tag = read_varint(&mut reader)?;
+        let instr = if tag == TAG_PUSH {
+            let mut table = [0x00; 1];
+            reader.read_exact(&mut table)?;
+            // a truth table has only 4 entries
+            if table[0] > 0x0F {
+                return Err(Error {
+                    message: format!("instruction {} pushes invalid truth table {:#04x}", index, table[0]),
+                    kind: ErrorKind::InvalidInstr,
+                });
+            }
+            Instr::Value(PushInstr::Push(IoTruthTable(table[0])))
+        } else if tag == TAG_READ {
+            let offset = un_zig_zag(read_varint(&mut reader)?);
+            Instr::Value(PushInstr::ReadThenPush { offset })
+        } else {
+            match tag.checked_sub(TAG_READ + 1).and_then(|i| OPS.get(i as usize)) {
+                Some(&op) => Instr::Operation(op),
+                None => {
+                    return Err(Error {
+                        message: format!("instruction {} has unknown tag {}", index, tag),
+                        kind: ErrorKind::InvalidInstr,
+                    });
+                }
+            }
+        };
+        instrs.push(instr);
+    }
+
+    // debug info
+    let mut has_debug_info = [0x00; 1];
+    reader.read_exact(&mut has_debug_info)?;
+    let debug_info = match has_debug_info[0] {
+        0x00 => None,
+        0x01 => {
+            let mut instr_sources = Vec::new();
+            for index in 0..instr_count {
+                let start = read_len(&mut reader)?;
+                if start == 0 {
+                    instr_sources.push(None);
+                    continue;
+                }
+                let start = start - 1;
+                let end = start.checked_add(read_len(&mut reader)?)
+                    .ok_or_else(|| Error {
+                        message: format!("source of instruction {} ends beyond any source", index),
+                        kind: ErrorKind::InvalidDebugInfo,
+                    })?;
+                instr_sources.push(Some(SourceRange { start, end }));
+            }
+            Some(DebugInfo { instr_sources })
+        }
+        flag => {
+            return Err(Error {
+                message: format!("invalid debug info flag {}", flag),
+                kind: ErrorKind::InvalidDebugInfo,
+            });
+        }
+    };
+
+    verify::verify(&instrs).map_err(|e| Error {
+        message: e.message,
+        kind: ErrorKind::Malformed,
+    })?;
+    Ok(CompiledProgram::new(activation, instrs, debug_info))
+}
+
+/// Map signed integers onto unsigned ones, such that those of small magnitude
+/// stay small.
+fn zig_zag(n: i128) -> u128 {
+    ((n << 1) ^ (n >> 127)) as u128
+}
+
+fn un_zig_zag(n: u128) -> i128 {
+    (n >> 1) as i128 ^ -((n & 0x1) as i128)
+}
+
+fn write_varint<W: Write>(writer: &mut W, mut n: u128) -> io::Result<()> {
+    loop {
+        let byte = (n & 0x7F) as u8;
+        n >>= 7;
+        if n == 0 {
+            return writer.write_all(&[byte]);
+        }
+        writer.write_all(&[byte | 0x80])?;
+    }
+}
+
+fn read_varint<R: Read>(reader: &mut R) -> Result<u128, Error> {
+    let mut n: u128 = 0;
+    let mut shift: u32 = 0;
+    loop {
+        let mut byte = [0x00; 1];
+        reader.read_exact(&mut byte)?;
+        let bits = (byte[0] & 0x7F) as u128;
+
+        // reject bits beyond 128, and needlessly long encodings
+        if shift >= 128 || (bits << shift) >> shift != bits || (byte[0] == 0x00 && shift > 0) {
+            return Err(Error {
+                message: "invalid varint".to_owned(),
+                kind: ErrorKind::InvalidVarint,
+            });
+        }
+        n |= bits << shift;
+        shift += 7;
+
+        if byte[0] & 0x80 == 0 {
+            return Ok(n);
+        }
+    }
+}
+
+/// Read a varint which counts something held in memory.
+fn read_len<R: Read>(reader: &mut R) -> Result<usize, Error> {
+    let n = read_varint(reader)?;
+    if n > usize::MAX as u128 {
+        return Err(Error {
+            message: format!("length {} is too large", n),
+            kind: ErrorKind::InvalidVarint,
+        });
+    }
+    Ok(n as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::compile::compile;
+
+    fn write(program: &CompiledProgram) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_program(program, &mut bytes).unwrap();
+        bytes
+    }
+
+    fn read_kind(bytes: &[u8]) -> Option<ErrorKind> {
+        read_program(bytes).err().map(|e| e.kind)
+    }
+
+    #[test]
+    fn round_trips() {
+        let code = "1101: & | <1 ~ >3 ^ <fffffffff I";
+        let program = compile(code).unwrap();
+        assert_eq!(read_program(&write(&program)[..]).unwrap(), program);
+
+        let stripped = CompiledProgram {
+            debug_info: None,
+            ..program
+        };
+        // further data may follow
+        let mut bytes = write(&stripped);
+        bytes.push(0xFF);
+        assert_eq!(read_program(&bytes[..]).unwrap(), stripped);
+    }
+
+    #[test]
+    fn rejects_invalid_encodings() {
+        let program = compile("1: ^ <1 O").unwrap();
+        let bytes = write(&program);
+
+        assert_eq!(read_kind(&bytes[..bytes.len() - 1]), Some(ErrorKind::Io));
+        assert_eq!(read_kind(b"BPBX\x01\x00\x00\x00"), Some(ErrorKind::BadMagic));
+        assert_eq!(read_kind(b"BPBC\x02\x00\x00\x00"), Some(ErrorKind::UnsupportedVersion));
+        // one activation bit, with a bit set beyond it
+        assert_eq!(read_kind(b"BPBC\x01\x00\x00\x00\x01\x03"), Some(ErrorKind::InvalidActivation));
+
+        let instrs = |instrs: &[u8]| {
+            let mut bytes = b"BPBC\x01\x00\x00\x00\x00".to_vec();
+            bytes.extend_from_slice(instrs);
+            bytes.push(0x00);
+            bytes
+        };
+        assert!(read_program(&instrs(&[0x01, 0x00, 0x0A])[..]).is_ok());
+        assert_eq!(read_kind(&instrs(&[0x01, 0x00, 0x1A])), Some(ErrorKind::InvalidInstr));
+        assert_eq!(read_kind(&instrs(&[0x01, 0x09])), Some(ErrorKind::InvalidInstr));
+        // `and` of a single value
+        assert_eq!(read_kind(&instrs(&[0x02, 0x00, 0x0A, 0x02])), Some(ErrorKind::Malformed));
+        assert_eq!(read_kind(&instrs(&[0x00])), Some(ErrorKind::Malformed));
+    }
+}
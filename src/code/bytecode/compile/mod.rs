@@ -73,6 +73,14 @@ pub fn compile(code: &str) -> Result<CompiledProgram, Error> {
                     }
                     let expression: ExprSubprogram = expressions.remove(0);
 
+                    // keep where each instruction came from
+                    let debug_info = DebugInfo {
+                        instr_sources: expression.iter()
+                            .map(|instr| instr.span().offsets_within(code)
+                                .map(|(start, end)| SourceRange { start, end }))
+                            .collect(),
+                    };
+
                     // de-span the expression instructions
                     let instrs: Vec<Instr> = expression
                         .into_iter()
@@ -103,10 +111,7 @@ pub fn compile(code: &str) -> Result<CompiledProgram, Error> {
                     };
 
                     // compose the compiled program
-                    let program = CompiledProgram {
-                        activation,
-                        instrs,
-                    };
+                    let program = CompiledProgram::new(activation, instrs, Some(debug_info));
 
                     Ok(program)
                 })
@@ -0,0 +1,147 @@
+use super::*;
+use super::verify::verify;
+use crate::memory::background::Background;
+use crate::runtime::eval;
+
+use std::collections::BTreeSet;
+
+/// Most distinct read offsets for which I/O usage is found exactly, by
+/// evaluating the rule for every combination of bits read.
+const MAX_EXACT_OFFSETS: usize = 12;
+
+/// Facts about a behavior rule, derived from its instructions.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default)]
+pub struct Metadata {
+    /// Distinct memory read offsets, in ascending order.
+    pub offsets: Vec<i128>,
+    /// Whether the input can affect the result.
+    pub uses_input: bool,
+    /// Whether the output can affect the result.
+    pub uses_output: bool,
+    /// Whether each bit reads itself.
+    pub reads_self: bool,
+    /// Whether the rule is stable, as the readme defines it: if all of a
+    /// bit's reads yield no, it evaluates to no and performs no I/O. Memory
+    /// of all no then never changes.
+    ///
+    /// Malformed rules are never stable.
+    pub stable: bool,
+}
+
+impl Metadata {
+    /// Derive the facts about a rule.
+    ///
+    /// If the rule reads too many distinct offsets to evaluate exhaustively,
+    /// or is malformed, I/O is considered used if any push depends on it.
+    pub fn of(instrs: &[Instr]) -> Self {
+        let offsets: Vec<i128> = instrs.iter()
+            .filter_map(|instr| match *instr {
+                Instr::Value(PushInstr::ReadThenPush { offset }) => Some(offset),
+                _ => None,
+            })
+            .collect::<BTreeSet<i128>>()
+            .into_iter()
+            .collect();
+        let reads_self = offsets.binary_search(&0).is_ok();
+
+        let well_formed = verify(instrs).is_ok();
+        let stable = well_formed && eval::is_stable(instrs, &Background::zeros());
+
+        let (uses_input, uses_output) = if well_formed && offsets.len() <= MAX_EXACT_OFFSETS {
+            let mut stack = Vec::new();
+            let mut uses = (false, false);
+            for bits in 0..(0x1_u32 << offsets.len()) {
+                let table = eval::evaluate(instrs, &mut stack, |offset| {
+                    let i = offsets.binary_search(&offset).unwrap();
+                    bits & (0x1 << i) != 0
+                });
+                uses.0 |= eval::does_input(table, false) || eval::does_input(table, true);
+                uses.1 |= eval::does_output(table);
+                if uses == (true, true) {
+                    break;
+                }
+            }
+            uses
+        } else {
+            instrs.iter()
+                .filter_map(|instr| match *instr {
+                    Instr::Value(PushInstr::Push(table)) => Some(table),
+                    _ => None,
+                })
+                .fold((false, false), |(input, output), table| (
+                    input || eval::does_input(table, false) || eval::does_input(table, true),
+                    output || eval::does_output(table),
+                ))
+        };
+
+        Metadata {
+            offsets,
+            uses_input,
+            uses_output,
+            reads_self,
+            stable,
+        }
+    }
+
+    /// The lowest offset read, if any.
+    pub fn min_offset(&self) -> Option<i128> {
+        self.offsets.first().copied()
+    }
+
+    /// The highest offset read, if any.
+    pub fn max_offset(&self) -> Option<i128> {
+        self.offsets.last().copied()
+    }
+
+    /// Greatest distance of any offset read, which is also the furthest a
+    /// change can spread per tick.
+    pub fn radius(&self) -> u128 {
+        self.offsets.iter()
+            .map(|offset| offset.unsigned_abs())
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::compile::compile;
+
+    fn metadata(code: &str) -> Metadata {
+        compile(code).unwrap().metadata().clone()
+    }
+
+    #[test]
+    fn finds_offsets() {
+        let metadata = metadata("1: & | <1 >3 ^ <1 *");
+        assert_eq!(metadata.offsets, vec![-1, 0, 3]);
+        assert!(metadata.reads_self);
+        assert_eq!((metadata.min_offset(), metadata.max_offset()), (Some(-1), Some(3)));
+        assert_eq!(metadata.radius(), 3);
+        assert_eq!(Metadata::of(&[]).min_offset(), None);
+    }
+
+    #[test]
+    fn finds_io_usage_exactly() {
+        let uses = |code| {
+            let metadata = metadata(code);
+            (metadata.uses_input, metadata.uses_output)
+        };
+        assert_eq!(uses("1: <1"), (false, false));
+        assert_eq!(uses("1: ^ <1 I"), (true, false));
+        assert_eq!(uses("1: ^ <1 O"), (false, true));
+        // the I/O cancels out, though pushes depend on it
+        assert_eq!(uses("1: ^ <1 ^ I I"), (false, false));
+    }
+
+    #[test]
+    fn stable_as_the_readme_defines() {
+        assert!(metadata("1: <1").stable);
+        assert!(!metadata("1: ~<1").stable);
+        assert!(!metadata("1: | <1 I").stable);
+        // I/O which cannot matter when every read yields no
+        assert!(metadata("1: & <1 I").stable);
+        assert!(!Metadata::of(&[]).stable);
+    }
+}
@@ -1,13 +1,90 @@
 
 pub mod compile;
 
+pub mod binary;
+
+pub mod verify;
+
+pub mod asm;
+
+pub mod metadata;
+
 use super::truthtable::*;
 
 /// Fully formed runnable bytecode program.
 #[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
 pub struct CompiledProgram {
     pub activation: Vec<bool>,
-    pub instrs: Vec<Instr>,
+    instrs: Vec<Instr>,
+    /// Facts about the behavior rule, derived from its instructions.
+    metadata: metadata::Metadata,
+    /// Source location of each instruction, if retained.
+    pub debug_info: Option<DebugInfo>,
+}
+
+impl CompiledProgram {
+    /// Program of some instructions, deriving the metadata about them.
+    pub fn new(activation: Vec<bool>, instrs: Vec<Instr>, debug_info: Option<DebugInfo>) -> Self {
+        CompiledProgram {
+            activation,
+            metadata: metadata::Metadata::of(&instrs),
+            instrs,
+            debug_info,
+        }
+    }
+
+    pub fn instrs(&self) -> &[Instr] {
+        &self.instrs
+    }
+
+    /// Facts about the behavior rule, derived from its instructions.
+    pub fn metadata(&self) -> &metadata::Metadata {
+        &self.metadata
+    }
+
+    /// Source location of the instruction at some index, if known.
+    pub fn instr_source(&self, index: usize) -> Option<SourceRange> {
+        self.debug_info.as_ref()
+            .and_then(|debug_info| debug_info.instr_source(index))
+    }
+}
+
+/// Mapping from bytecode back to the source code it was compiled from.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default)]
+pub struct DebugInfo {
+    /// Source location of the instruction at each index, if known.
+    pub instr_sources: Vec<Option<SourceRange>>,
+}
+
+impl DebugInfo {
+    /// Source location of the instruction at some index, if known.
+    pub fn instr_source(&self, index: usize) -> Option<SourceRange> {
+        self.instr_sources.get(index).copied().flatten()
+    }
+}
+
+/// Location within source code, as the byte offsets `[start, end)`.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct SourceRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl SourceRange {
+    /// The source code within the range, if the range lies within it.
+    pub fn text<'a>(&self, code: &'a str) -> Option<&'a str> {
+        code.get(self.start..self.end)
+    }
+
+    /// Line and column of the start of the range, both starting at 1, with
+    /// columns counted in characters, if the start lies within the source
+    /// code and on a character boundary.
+    pub fn line_column(&self, code: &str) -> Option<(usize, usize)> {
+        let before = code.get(..self.start)?;
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        Some((line, before[line_start..].chars().count() + 1))
+    }
 }
 
 /// Bytecode instruction.
@@ -81,4 +158,23 @@ enum_from_samey! {
         Same,
         Neither,
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locates_source_ranges() {
+        let code = "1:\n  ^ <1 >é\n";
+        let range = |start, end| SourceRange { start, end };
+        assert_eq!(range(0, 1).line_column(code), Some((1, 1)));
+        assert_eq!(range(5, 6).line_column(code), Some((2, 3)));
+        assert_eq!(range(5, 6).text(code), Some("^"));
+        assert_eq!(range(13, 14).line_column(code), Some((2, 10)));
+        assert_eq!(range(14, 14).line_column(code), Some((3, 1)));
+        // within a character, or beyond the code
+        assert_eq!(range(12, 13).line_column(code), None);
+        assert_eq!(range(12, 13).text(code), None);
+        assert_eq!(range(15, 16).line_column(code), None);
+    }
+}
@@ -0,0 +1,158 @@
+use super::*;
+
+/// Error found verifying bytecode.
+#[derive(Debug, Clone)]
+pub struct Error {
+    pub message: String,
+    pub kind: ErrorKind,
+    /// Index of the offending instruction, or the number of instructions if
+    /// the program as a whole is at fault.
+    pub instr_index: usize,
+    /// Source location of the offending instruction, if known.
+    pub source: Option<SourceRange>,
+}
+
+impl Error {
+    /// The message, prefixed by where in the source code the offending
+    /// instruction came from, if known.
+    pub fn describe(&self, code: &str) -> String {
+        let location = self.source
+            .and_then(|source| Some((source.line_column(code)?, source.text(code)?)));
+        match location {
+            Some(((line, column), text)) => format!("{}:{} (`{}`): {}", line, column, text, self.message),
+            None => self.message.clone(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum ErrorKind {
+    /// An operation pops more values than the stack holds.
+    Underflow,
+    /// The program leaves more than one value on the stack.
+    LeftoverValues,
+    /// The program leaves no value on the stack.
+    NoValue,
+}
+
+/// Stack effects of well-formed bytecode.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct StackInfo {
+    /// Greatest number of values on the stack at once.
+    pub max_depth: usize,
+}
+
+/// Check that instructions evaluate to exactly one value without underflowing
+/// the stack, by simulating their effect on its depth.
+pub fn verify(instrs: &[Instr]) -> Result<StackInfo, Error> {
+    // index of the instruction which pushed each value on the stack
+    let mut stack: Vec<usize> = Vec::new();
+    let mut max_depth: usize = 0;
+
+    for (index, instr) in instrs.iter().enumerate() {
+        if let Instr::Operation(op) = *instr {
+            if stack.len() < op.arity() {
+                return Err(Error {
+                    message: format!(
+                        "instruction {} ({:?}) pops {} values, but the stack holds {}",
+                        index, op, op.arity(), stack.len(),
+                    ),
+                    kind: ErrorKind::Underflow,
+                    instr_index: index,
+                    source: None,
+                });
+            }
+            stack.truncate(stack.len() - op.arity());
+        }
+        stack.push(index);
+        max_depth = max_depth.max(stack.len());
+    }
+
+    match stack.len() {
+        0 => Err(Error {
+            message: "program leaves no value on the stack".to_owned(),
+            kind: ErrorKind::NoValue,
+            instr_index: instrs.len(),
+            source: None,
+        }),
+        1 => Ok(StackInfo { max_depth }),
+        // the result is on top, so blame the value beneath it
+        len => Err(Error {
+            message: format!(
+                "program leaves {} values on the stack, the first extra pushed by instruction {}",
+                len, stack[len - 2],
+            ),
+            kind: ErrorKind::LeftoverValues,
+            instr_index: stack[len - 2],
+            source: None,
+        }),
+    }
+}
+
+/// Verify a program's instructions, locating any error in the source code
+/// using the program's debug info.
+pub fn verify_program(program: &CompiledProgram) -> Result<StackInfo, Error> {
+    verify(&program.instrs).map_err(|error| Error {
+        source: program.instr_source(error.instr_index),
+        ..error
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::compile::compile;
+
+    const READ: Instr = Instr::Value(PushInstr::ReadThenPush { offset: 1 });
+    const PUSH: Instr = Instr::Value(PushInstr::Push(IoTruthTable(0x0C)));
+    const AND: Instr = Instr::Operation(OpInstr::Both);
+    const NOT: Instr = Instr::Operation(OpInstr::Not);
+
+    #[test]
+    fn finds_stack_depth() {
+        assert_eq!(verify(&[READ]).unwrap().max_depth, 1);
+        assert_eq!(verify(&[READ, NOT, PUSH, PUSH, AND, AND]).unwrap().max_depth, 3);
+    }
+
+    #[test]
+    fn rejects_underflow() {
+        let error = verify(&[READ, NOT, AND, PUSH]).unwrap_err();
+        assert_eq!((error.kind, error.instr_index), (ErrorKind::Underflow, 2));
+        let error = verify(&[NOT]).unwrap_err();
+        assert_eq!((error.kind, error.instr_index), (ErrorKind::Underflow, 0));
+    }
+
+    #[test]
+    fn rejects_leftover_values() {
+        // the value beneath the result is blamed
+        let error = verify(&[READ, PUSH, PUSH, AND]).unwrap_err();
+        assert_eq!((error.kind, error.instr_index), (ErrorKind::LeftoverValues, 0));
+        let error = verify(&[READ, PUSH, AND, READ, PUSH, NOT]).unwrap_err();
+        assert_eq!((error.kind, error.instr_index), (ErrorKind::LeftoverValues, 3));
+    }
+
+    #[test]
+    fn rejects_no_value() {
+        let error = verify(&[]).unwrap_err();
+        assert_eq!((error.kind, error.instr_index), (ErrorKind::NoValue, 0));
+    }
+
+    #[test]
+    fn locates_errors_in_source() {
+        let code = "1:\n  ^ <1 >1";
+        let mut program = compile(code).unwrap();
+        assert!(verify_program(&program).is_ok());
+
+        // drop the first operand of `^`
+        program.instrs.remove(0);
+        program.debug_info.as_mut().unwrap().instr_sources.remove(0);
+        let error = verify_program(&program).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Underflow);
+        assert_eq!(error.source.and_then(|source| source.text(code)), Some("^"));
+        assert_eq!(error.describe(code), format!("2:3 (`^`): {}", error.message));
+
+        program.debug_info = None;
+        let error = verify_program(&program).unwrap_err();
+        assert_eq!(error.describe(code), error.message);
+    }
+}
@@ -9,6 +9,24 @@ pub enum Span<'a> {
     AddrRange(usize, usize),
 }
 
+impl<'a> Span<'a> {
+    /// Byte offsets of the start and end of the span within the source code
+    /// it was taken from, if known.
+    pub fn offsets_within(self, code: &str) -> Option<(usize, usize)> {
+        let (start, end) = match self {
+            Span::None => return None,
+            Span::Slice(slice) => (slice.as_ptr() as usize, slice.as_ptr() as usize + slice.len()),
+            Span::AddrRange(a, b) => (a, b),
+        };
+
+        let base = code.as_ptr() as usize;
+        if start < base || end > base + code.len() || start > end {
+            return None;
+        }
+        Some((start - base, end - base))
+    }
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
 pub struct Spanned<'a, T>(pub T, pub Span<'a>);
 
@@ -4,6 +4,7 @@ extern crate nom;
 
 pub mod memory;
 pub mod code;
+pub mod runtime;
 
 use code::bytecode::compile;
 
@@ -0,0 +1,183 @@
+use super::node::*;
+use super::page::PageBits;
+
+use std::mem::size_of;
+use std::ops::{Index, IndexMut};
+
+/// Index of a node within an arena.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct NodeId(u32);
+
+/// Store of every node of a tree, contiguous and referenced by index, so that
+/// clearing or dropping the tree never recurses.
+///
+/// Slots of removed nodes are reused by later allocations.
+///
+/// Nodes may be shared between several trees in the same arena, each node
+/// counting the references to it, and are copied by `make_mut` before being
+/// modified while shared.
+#[derive(Clone)]
+pub struct Arena {
+    nodes: Vec<Node>,
+    /// Number of references to each node, or 0 for free slots.
+    refs: Vec<u32>,
+    free: Vec<NodeId>,
+    count: NodeCount,
+    /// Heap bytes allocated for the bits of pages.
+    page_bytes: usize,
+}
+
+impl Arena {
+    pub fn new() -> Self {
+        Arena {
+            nodes: Vec::new(),
+            refs: Vec::new(),
+            free: Vec::new(),
+            count: NodeCount::default(),
+            page_bytes: 0,
+        }
+    }
+
+    pub fn alloc(&mut self, node: Node) -> NodeId {
+        match &node {
+            Node::Page { bits, .. } => {
+                self.count.pages += 1;
+                self.page_bytes += bits.heap_bytes();
+            }
+            Node::Branch { .. } => self.count.branches += 1,
+        }
+
+        match self.free.pop() {
+            Some(id) => {
+                self.nodes[id.0 as usize] = node;
+                self.refs[id.0 as usize] = 1;
+                id
+            }
+            None => {
+                let id = NodeId(self.nodes.len() as u32);
+                self.nodes.push(node);
+                self.refs.push(1);
+                id
+            }
+        }
+    }
+
+    /// Add a reference to a node.
+    pub fn retain(&mut self, id: NodeId) {
+        self.refs[id.0 as usize] += 1;
+    }
+
+    /// Remove a reference to a node, freeing it once no references remain,
+    /// along with the references it held to its children.
+    pub fn release(&mut self, id: NodeId) {
+        let mut stack: Vec<NodeId> = vec![id];
+        while let Some(id) = stack.pop() {
+            if self.refs[id.0 as usize] > 1 {
+                self.refs[id.0 as usize] -= 1;
+                continue;
+            }
+            if let Node::Branch { children, .. } = &self[id] {
+                stack.extend(children.iter().flatten());
+            }
+            self.free(id);
+        }
+    }
+
+    /// A node which may be modified in place of another, being the node
+    /// itself unless it is shared, in which case a reference is moved to a
+    /// new copy of it.
+    pub fn make_mut(&mut self, id: NodeId) -> NodeId {
+        if self.refs[id.0 as usize] == 1 {
+            return id;
+        }
+
+        self.refs[id.0 as usize] -= 1;
+        let node = self[id].clone();
+        if let Node::Branch { children, .. } = &node {
+            for &child in children.iter().flatten() {
+                self.retain(child);
+            }
+        }
+        self.alloc(node)
+    }
+
+    /// Release a node for reuse, without releasing its children, which must
+    /// be its only reference.
+    pub fn free(&mut self, id: NodeId) {
+        debug_assert_eq!(self.refs[id.0 as usize], 1, "freeing a shared node");
+        self.refs[id.0 as usize] = 0;
+        // replace the node, so that its heap allocations are released now
+        match std::mem::replace(&mut self.nodes[id.0 as usize], Node::page(0)) {
+            Node::Page { bits, .. } => {
+                self.count.pages -= 1;
+                self.page_bytes -= bits.heap_bytes();
+            }
+            Node::Branch { .. } => self.count.branches -= 1,
+        }
+        self.free.push(id);
+    }
+
+    /// Modify the bits of a page.
+    pub fn update_page<R, F: FnOnce(&mut PageBits) -> R>(&mut self, id: NodeId, modify: F) -> R {
+        if let Node::Page { bits, .. } = &mut self.nodes[id.0 as usize] {
+            let before = bits.heap_bytes();
+            let result = modify(bits);
+            self.page_bytes = self.page_bytes - before + bits.heap_bytes();
+            result
+        } else {
+            panic!("node is not a page")
+        }
+    }
+
+    /// Release every node at once.
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.refs.clear();
+        self.free.clear();
+        self.count = NodeCount::default();
+        self.page_bytes = 0;
+    }
+
+    /// Number of live nodes of each kind.
+    pub fn count(&self) -> NodeCount {
+        self.count
+    }
+
+    /// Number of heap bytes allocated, including free slots.
+    pub fn heap_bytes(&self) -> usize {
+        self.nodes.capacity() * size_of::<Node>()
+            + self.refs.capacity() * size_of::<u32>()
+            + self.free.capacity() * size_of::<NodeId>()
+            + self.count.branches * size_of::<[Option<NodeId>; BRANCH_FACTOR]>()
+            + self.page_bytes
+    }
+
+    /// Number of layers of the subtree under a node.
+    pub fn layers(&self, id: NodeId) -> usize {
+        // stack of nodes yet to be visited, with their depth
+        let mut stack: Vec<(NodeId, usize)> = vec![(id, 1)];
+        let mut layers: usize = 0;
+
+        while let Some((id, depth)) = stack.pop() {
+            layers = layers.max(depth);
+            if let Node::Branch { children, .. } = &self[id] {
+                stack.extend(children.iter().flatten().map(|&child| (child, depth + 1)));
+            }
+        }
+        layers
+    }
+}
+
+impl Index<NodeId> for Arena {
+    type Output = Node;
+
+    fn index(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0 as usize]
+    }
+}
+
+impl IndexMut<NodeId> for Arena {
+    fn index_mut(&mut self, id: NodeId) -> &mut Node {
+        &mut self.nodes[id.0 as usize]
+    }
+}
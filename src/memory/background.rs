@@ -0,0 +1,128 @@
+use super::node::PAGE_SIZE;
+use super::twiddling::*;
+
+/// Periodic pattern held by every address which has not been written, in
+/// place of all no.
+///
+/// The pattern is kept at its shortest period, so equal backgrounds are
+/// equally represented.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Background {
+    /// Bit of each address modulo the period, starting from address 0.
+    pattern: Vec<bool>,
+}
+
+impl Background {
+    /// Every bit no.
+    pub fn zeros() -> Self {
+        Background {
+            pattern: vec![false],
+        }
+    }
+
+    /// Background repeating a pattern, whose first bit is at address 0.
+    ///
+    /// Panics if the pattern is empty.
+    pub fn new(pattern: &[bool]) -> Self {
+        assert!(!pattern.is_empty(), "background pattern is empty");
+
+        let period = (1..=pattern.len())
+            .find(|&period| {
+                pattern.len().is_multiple_of(period)
+                    && pattern.iter().enumerate().all(|(i, &bit)| bit == pattern[i % period])
+            })
+            .unwrap();
+        Background {
+            pattern: pattern[..period].to_vec(),
+        }
+    }
+
+    /// Number of addresses after which the pattern repeats.
+    pub fn period(&self) -> usize {
+        self.pattern.len()
+    }
+
+    /// Bits of the pattern for one period, starting from address 0.
+    pub fn pattern(&self) -> &[bool] {
+        &self.pattern
+    }
+
+    pub fn is_zeros(&self) -> bool {
+        self.pattern == [false]
+    }
+
+    /// Bit of the background at an address.
+    pub fn get(&self, address: i128) -> bool {
+        if self.pattern.len() == 1 {
+            return self.pattern[0];
+        }
+        self.pattern[address.rem_euclid(self.pattern.len() as i128) as usize]
+    }
+
+    /// Background of the page starting at some address, as words.
+    pub fn page_words(&self, base: i128) -> [u8; PAGE_SIZE] {
+        if self.pattern.len() == 1 {
+            return [if self.pattern[0] { 0xFF } else { 0x00 }; PAGE_SIZE];
+        }
+
+        let mut words = [0x00; PAGE_SIZE];
+        let mut phase = base.rem_euclid(self.pattern.len() as i128) as usize;
+        for i in 0..(8 * PAGE_SIZE) {
+            set_word_bit(&mut words[i / 8], (i % 8) as u8, self.pattern[phase]);
+            phase += 1;
+            if phase == self.pattern.len() {
+                phase = 0;
+            }
+        }
+        words
+    }
+
+    /// Background with every bit translated by an offset.
+    pub fn shifted(&self, offset: i128) -> Background {
+        let period = self.pattern.len() as i128;
+        Background {
+            pattern: (0..period).map(|i| self.get(i - offset.rem_euclid(period))).collect(),
+        }
+    }
+
+    /// Background mirrored about an address, such that each bit at `a` moves
+    /// to `2 * center - a`.
+    pub fn reflected(&self, center: i128) -> Background {
+        let period = self.pattern.len() as i128;
+        let double_center = 2 * center.rem_euclid(period);
+        Background {
+            pattern: (0..period).map(|i| self.get(double_center - i)).collect(),
+        }
+    }
+
+    /// Combine two backgrounds bit by bit, by an operation on words.
+    ///
+    /// The period of the result is at most the lowest common multiple of the
+    /// two periods.
+    pub fn combine<F>(&self, other: &Background, op: F) -> Background
+        where F: Fn(u8, u8) -> u8
+    {
+        let word = |bit: bool| if bit { 0xFF } else { 0x00 };
+        let period = lcm(self.pattern.len(), other.pattern.len());
+        let pattern: Vec<bool> = (0..period as i128)
+            .map(|i| op(word(self.get(i)), word(other.get(i))) & 0x1 != 0)
+            .collect();
+        Background::new(&pattern)
+    }
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::zeros()
+    }
+}
+
+fn lcm(a: usize, b: usize) -> usize {
+    let (mut x, mut y) = (a, b);
+    while y != 0 {
+        let r = x % y;
+        x = y;
+        y = r;
+    }
+    a / x * b
+}
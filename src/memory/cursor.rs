@@ -0,0 +1,274 @@
+use super::arena::{Arena, NodeId};
+use super::node::*;
+use super::twiddling::*;
+use super::page::PageBits;
+use super::read::page_bit_index;
+use super::{Memory, write};
+
+/// Nodes from the root down to the node last reached by a search, so that
+/// a search nearby resumes from their lowest common ancestor rather than
+/// from the root.
+#[derive(Clone, Default)]
+struct Path(Vec<NodeId>);
+
+impl Path {
+    /// Find the bits of the page containing an address, if it exists.
+    fn search_page<'a>(&mut self, arena: &'a Arena, root: NodeId, address: i128) -> Option<&'a PageBits> {
+        let contains = |id: NodeId| {
+            let node = &arena[id];
+            node.row_index() == node.row_index_of_address(address)
+        };
+
+        if self.0.first() != Some(&root) {
+            self.0.clear();
+            self.0.push(root);
+        }
+        // every ancestor of a node containing the address contains it too
+        while self.0.len() > 1 && !contains(*self.0.last().unwrap()) {
+            self.0.pop();
+        }
+
+        loop {
+            let id = *self.0.last().unwrap();
+            if !contains(id) {
+                return None;
+            }
+            match &arena[id] {
+                Node::Page { bits, .. } => return Some(bits),
+                Node::Branch { level, children, .. } => {
+                    self.0.push(children[child_index(address, ChildOfBranchLevel(*level))]?);
+                }
+            }
+        }
+    }
+
+    /// Forget the path, whose nodes may have been replaced or freed.
+    fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+/// Read-only view of memory relative to a position, which caches the path
+/// to the page last read, so that reads near one another skip most or all of
+/// searching the tree.
+///
+/// Reads beyond the address space yield no.
+#[derive(Clone)]
+pub struct Cursor<'a> {
+    memory: &'a Memory,
+    position: i128,
+    path: Path,
+}
+
+impl<'a> Cursor<'a> {
+    pub fn new(memory: &'a Memory, position: i128) -> Self {
+        Cursor {
+            memory,
+            position,
+            path: Path::default(),
+        }
+    }
+
+    pub fn position(&self) -> i128 {
+        self.position
+    }
+
+    /// Move to an absolute address.
+    pub fn move_to(&mut self, position: i128) {
+        self.position = position;
+    }
+
+    /// Move relative to the current position.
+    ///
+    /// Panics if this would move beyond the address space.
+    pub fn move_by(&mut self, delta: i128) {
+        self.position = self.position.checked_add(delta)
+            .expect("cursor moved beyond the address space");
+    }
+
+    /// Read the bit at an offset from the current position.
+    pub fn get(&mut self, offset: i128) -> bool {
+        let address = match self.position.checked_add(offset) {
+            Some(address) => address,
+            None => return false,
+        };
+
+        let memory = self.memory;
+        self.path.search_page(&memory.arena, memory.root, address)
+            .map(|bits| bits.get(page_bit_index(address)))
+            .unwrap_or(false)
+            ^ self.memory.background.get(address)
+    }
+}
+
+/// Page held by a mutable cursor, to be written back when it moves on.
+struct CachedPage {
+    row_index: i128,
+    /// Bits of the page as stored, differing from the background, decoded as
+    /// words.
+    bits: [u8; PAGE_SIZE],
+    /// Whether the page exists in the tree.
+    exists: bool,
+    /// Whether the bits have been changed since being loaded.
+    dirty: bool,
+}
+
+/// Mutable view of memory relative to a position, which holds a copy of the
+/// page last accessed, and the path to it, so that accesses near one another
+/// skip most or all of searching the tree.
+///
+/// Changes are written back when the cursor moves to another page, upon
+/// `flush`, and when it is dropped. Reads beyond the address space yield no,
+/// and writes there are ignored.
+pub struct CursorMut<'a> {
+    memory: &'a mut Memory,
+    position: i128,
+    page: Option<CachedPage>,
+    /// Path to the cached page, forgotten whenever it is written back.
+    path: Path,
+}
+
+impl<'a> CursorMut<'a> {
+    pub fn new(memory: &'a mut Memory, position: i128) -> Self {
+        CursorMut {
+            memory,
+            position,
+            page: None,
+            path: Path::default(),
+        }
+    }
+
+    pub fn position(&self) -> i128 {
+        self.position
+    }
+
+    /// Move to an absolute address.
+    pub fn move_to(&mut self, position: i128) {
+        self.position = position;
+    }
+
+    /// Move relative to the current position.
+    ///
+    /// Panics if this would move beyond the address space.
+    pub fn move_by(&mut self, delta: i128) {
+        self.position = self.position.checked_add(delta)
+            .expect("cursor moved beyond the address space");
+    }
+
+    /// Read the bit at an offset from the current position.
+    pub fn get(&mut self, offset: i128) -> bool {
+        match self.position.checked_add(offset) {
+            Some(address) => {
+                let background = self.memory.background.get(address);
+                let page = self.load(address);
+                get_word_bit(page.bits[child_index(address, WordLevel)], child_index(address, BitLevel) as u8)
+                    ^ background
+            }
+            None => false,
+        }
+    }
+
+    /// Write the bit at an offset from the current position.
+    pub fn set(&mut self, offset: i128, bit: bool) {
+        if let Some(address) = self.position.checked_add(offset) {
+            let bit = bit ^ self.memory.background.get(address);
+            let page = self.load(address);
+            set_word_bit(&mut page.bits[child_index(address, WordLevel)], child_index(address, BitLevel) as u8, bit);
+            page.dirty = true;
+        }
+    }
+
+    /// Write any changes back to memory.
+    pub fn flush(&mut self) {
+        if let Some(page) = self.page.as_mut() {
+            if !page.dirty {
+                return;
+            }
+            page.dirty = false;
+
+            // writing no to an untouched page changes nothing
+            if !page.exists && page.bits.iter().all(|&word| word == 0x00) {
+                return;
+            }
+
+            let address = base_address(page.row_index, PageLevel);
+            let memory = &mut *self.memory;
+            write::update_page(&mut memory.arena, &mut memory.root, address, |bits| {
+                *bits = PageBits::from_dense(&page.bits);
+            });
+            write::prune_page(&mut memory.arena, &mut memory.root, address);
+            self.path.clear();
+            page.exists = self.path.search_page(&memory.arena, memory.root, address).is_some();
+        }
+    }
+
+    /// The cached page containing an address, loading it first if necessary.
+    fn load(&mut self, address: i128) -> &mut CachedPage {
+        let page_row_index = row_index(address, PageLevel);
+        if self.page.as_ref().map(|page| page.row_index) != Some(page_row_index) {
+            self.flush();
+
+            let existing = self.path.search_page(&self.memory.arena, self.memory.root, address);
+            self.page = Some(CachedPage {
+                row_index: page_row_index,
+                bits: existing.map(PageBits::to_dense).unwrap_or([0x00; PAGE_SIZE]),
+                exists: existing.is_some(),
+                dirty: false,
+            });
+        }
+        self.page.as_mut().unwrap()
+    }
+}
+
+impl<'a> Drop for CursorMut<'a> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sparse_memory() -> Memory {
+        let mut memory = Memory::new();
+        for &address in &[i128::MIN, -1 << 90, -70_000, -1, 0, 8191, 8192, 1 << 40, i128::MAX] {
+            memory.set_bit(address, true);
+        }
+        memory
+    }
+
+    #[test]
+    fn reads_match_memory_across_the_tree() {
+        let memory = sparse_memory();
+        let mut cursor = memory.cursor(0);
+        // alternate between distant addresses, so the path retreats and
+        // descends through different branches
+        for &address in &[8191, -1 << 90, 8192, i128::MIN, 3, -70_000, i128::MAX, 1 << 40, -1, 1 << 41] {
+            cursor.move_to(address);
+            for offset in -2..=2 {
+                let expected = address.checked_add(offset).is_some_and(|a| memory.get_bit(a));
+                assert_eq!(cursor.get(offset), expected, "address {} offset {}", address, offset);
+            }
+        }
+    }
+
+    #[test]
+    fn writes_match_memory_across_the_tree() {
+        let mut expected = sparse_memory();
+        let mut memory = sparse_memory();
+        {
+            let mut cursor = memory.cursor_mut(0);
+            for &address in &[8191, -1 << 90, 8192, 5, 1 << 40, -1 << 90, 8190] {
+                cursor.move_to(address);
+                let bit = !cursor.get(1);
+                cursor.set(1, bit);
+                expected.set_bit(address + 1, bit);
+                cursor.set(0, false);
+                expected.set_bit(address, false);
+                assert!(!cursor.get(0));
+            }
+        }
+        assert!(memory == expected);
+    }
+}
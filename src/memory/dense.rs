@@ -0,0 +1,249 @@
+use super::background::Background;
+use super::store::{BitStore, DirectCursor};
+
+use std::convert::TryFrom;
+use std::hash::{Hash, Hasher};
+use std::mem::size_of;
+
+/// Lowest index of any word.
+const MIN_WORD_INDEX: i128 = i128::MIN >> 6;
+
+/// Highest index of any word.
+const MAX_WORD_INDEX: i128 = i128::MAX >> 6;
+
+/// Most words a window may hold, being as many as could be allocated.
+const MAX_WORDS: i128 = (isize::MAX as usize / size_of::<u64>()) as i128;
+
+/// Memory stored as a single contiguous window of words, which grows in
+/// whichever direction bits are set beyond it.
+///
+/// Fast for compact patterns, but allocates every word between the lowest
+/// and highest set bits ever written, so unsuited to patterns spread far
+/// apart.
+#[derive(Clone, Debug)]
+pub struct DenseMemory {
+    /// Index of the first word of the window, the word at index `i` holding
+    /// the bits of addresses `64 * i` to `64 * i + 63`.
+    first_word_index: i128,
+    /// How each bit differs from the background.
+    words: Vec<u64>,
+    background: Background,
+}
+
+impl DenseMemory {
+    pub fn new() -> Self {
+        DenseMemory::with_background(Background::zeros())
+    }
+
+    /// Memory holding a background at every address.
+    pub fn with_background(background: Background) -> Self {
+        DenseMemory {
+            first_word_index: 0,
+            words: Vec::new(),
+            background,
+        }
+    }
+
+    pub fn background(&self) -> &Background {
+        &self.background
+    }
+
+    pub fn get_bit(&self, address: i128) -> bool {
+        self.word_position(address.div_euclid(64))
+            .map(|i| self.words[i] & (0x1 << address.rem_euclid(64)) != 0)
+            .unwrap_or(false)
+            ^ self.background.get(address)
+    }
+
+    /// Panics if the window would need to grow beyond what could ever be
+    /// allocated, rather than truncate it.
+    pub fn set_bit(&mut self, address: i128, bit: bool) {
+        let bit = bit ^ self.background.get(address);
+        let word_index = address.div_euclid(64);
+        if bit {
+            self.cover(word_index);
+        }
+
+        // writing no beyond the window changes nothing
+        if let Some(i) = self.word_position(word_index) {
+            if bit {
+                self.words[i] |= 0x1 << address.rem_euclid(64);
+            } else {
+                self.words[i] &= !(0x1 << address.rem_euclid(64));
+            }
+        }
+    }
+
+    /// Iterate over the addresses of bits differing from the background, in
+    /// ascending order.
+    ///
+    /// Reverse the iterator for descending order.
+    pub fn ones(&self) -> impl DoubleEndedIterator<Item=i128> + '_ {
+        let first_word_index = self.first_word_index;
+        self.words.iter()
+            .enumerate()
+            .filter(|&(_, &word)| word != 0x0)
+            .flat_map(move |(i, &word)| {
+                let base = 64 * (first_word_index + i as i128);
+                (0..64)
+                    .filter(move |&bit_index| word & (0x1 << bit_index) != 0)
+                    .map(move |bit_index| base + bit_index)
+            })
+    }
+
+    /// Number of heap bytes allocated for the window.
+    pub fn heap_bytes(&self) -> usize {
+        self.words.capacity() * size_of::<u64>()
+    }
+
+    /// Position within the window of the word at some index, if covered.
+    fn word_position(&self, word_index: i128) -> Option<usize> {
+        let i = word_index - self.first_word_index;
+        if (0..self.words.len() as i128).contains(&i) {
+            Some(i as usize)
+        } else {
+            None
+        }
+    }
+
+    /// Grow the window to cover the word at some index.
+    ///
+    /// The window at least doubles each time it grows, up to the most words it
+    /// may hold, so that setting bits one after another in either direction
+    /// takes amortized constant time.
+    fn cover(&mut self, word_index: i128) {
+        let len = self.words.len() as i128;
+        // doubling alone never exceeds the most words the window may hold
+        let double = len.min(MAX_WORDS - len);
+        if len == 0 {
+            self.first_word_index = word_index;
+            self.words.push(0x0);
+        } else if word_index < self.first_word_index {
+            let last_word_index = self.first_word_index + len - 1;
+            let grow = (self.first_word_index - word_index).max(double);
+            let first_word_index = (self.first_word_index - grow).max(MIN_WORD_INDEX);
+            let mut words = vec![0x0; window_len(first_word_index, last_word_index)];
+            words[(self.first_word_index - first_word_index) as usize..].copy_from_slice(&self.words);
+            self.first_word_index = first_word_index;
+            self.words = words;
+        } else if word_index >= self.first_word_index + len {
+            let last_word_index = self.first_word_index + len - 1;
+            let grow = (word_index - last_word_index).max(double);
+            let last_word_index = (last_word_index + grow).min(MAX_WORD_INDEX);
+            self.words.resize(window_len(self.first_word_index, last_word_index), 0x0);
+        }
+    }
+}
+
+/// Number of words in a window from one word index to another, inclusive.
+///
+/// Panics if this is more than the window may hold.
+fn window_len(first_word_index: i128, last_word_index: i128) -> usize {
+    let len = last_word_index - first_word_index + 1;
+    if len > MAX_WORDS {
+        panic!(
+            "dense memory cannot cover addresses {:#x} to {:#x}, as they are too far apart",
+            64 * first_word_index,
+            64 * last_word_index + 63,
+        );
+    }
+    usize::try_from(len).unwrap()
+}
+
+impl Default for DenseMemory {
+    fn default() -> Self {
+        DenseMemory::new()
+    }
+}
+
+/// Memories are equal if they hold the same bits, regardless of window.
+impl PartialEq for DenseMemory {
+    fn eq(&self, other: &DenseMemory) -> bool {
+        self.background == other.background && self.ones().eq(other.ones())
+    }
+}
+
+impl Eq for DenseMemory {}
+
+/// Consistent with equality, so independent of window.
+impl Hash for DenseMemory {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.background.hash(state);
+        for address in self.ones() {
+            address.hash(state);
+        }
+    }
+}
+
+impl BitStore for DenseMemory {
+    type Cursor<'a> = DirectCursor<'a, DenseMemory>;
+
+    fn with_background(background: Background) -> DenseMemory {
+        DenseMemory::with_background(background)
+    }
+
+    fn background(&self) -> &Background {
+        DenseMemory::background(self)
+    }
+
+    fn get_bit(&self, address: i128) -> bool {
+        DenseMemory::get_bit(self, address)
+    }
+
+    fn set_bit(&mut self, address: i128, bit: bool) {
+        DenseMemory::set_bit(self, address, bit);
+    }
+
+    fn cursor(&self, position: i128) -> DirectCursor<'_, DenseMemory> {
+        DirectCursor::new(self, position)
+    }
+
+    fn ones(&self) -> Box<dyn DoubleEndedIterator<Item=i128> + '_> {
+        Box::new(DenseMemory::ones(self))
+    }
+
+    fn heap_bytes(&self) -> usize {
+        DenseMemory::heap_bytes(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grows_in_both_directions() {
+        let mut memory = DenseMemory::new();
+        for &address in &[0, 1 << 20, -(1 << 20) - 1, 63, -64] {
+            memory.set_bit(address, true);
+        }
+        assert_eq!(memory.ones().collect::<Vec<_>>(), vec![-(1 << 20) - 1, -64, 0, 63, 1 << 20]);
+        assert_eq!(memory.ones().next_back(), Some(1 << 20));
+        assert!(memory.words.len() as i128 >= ((1 << 21) + 1) / 64);
+    }
+
+    #[test]
+    #[should_panic(expected = "too far apart")]
+    fn rejects_writes_far_above() {
+        let mut memory = DenseMemory::new();
+        memory.set_bit(0, true);
+        memory.set_bit(1 << 70, true);
+    }
+
+    #[test]
+    #[should_panic(expected = "too far apart")]
+    fn rejects_writes_far_below() {
+        let mut memory = DenseMemory::new();
+        memory.set_bit(0, true);
+        memory.set_bit(-(1 << 70) - 192, true);
+    }
+
+    #[test]
+    fn writes_far_apart_as_no_change_nothing() {
+        let mut memory = DenseMemory::new();
+        memory.set_bit(0, true);
+        memory.set_bit(1 << 70, false);
+        memory.set_bit(i128::MIN, false);
+        assert_eq!(memory.words.len(), 1);
+    }
+}
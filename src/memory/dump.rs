@@ -0,0 +1,182 @@
+use super::Memory;
+
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+/// Number of bytes shown on each line of a dump.
+pub const BYTES_PER_LINE: usize = 8;
+
+// format, one line per 64 bits:
+//
+//     +0x0000000000000040: 01 00 00 00 00 00 00 80  #....... ........ (...) .......#
+//
+// - the address of the line's first bit, as hex with an explicit sign, or
+//   when parsing, optionally as decimal
+// - the bits grouped by byte as hex, least significant bit at the lowest
+//   address
+// - the same bits as `.` for no and `#` for yes, in ascending address order
+//
+// when parsing, either the hex or the `.`/`#` view may be omitted, and lines
+// which are blank or begin with `;` are ignored.
+
+/// Error parsing a dump.
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub message: String,
+    /// Line number, starting at 1.
+    pub line: usize,
+}
+
+/// Render the address range `[start, end)` of memory as a dump.
+pub fn dump(memory: &Memory, start: i128, end: i128) -> String {
+    let bits = memory.read_range(start, end);
+
+    let mut text = String::new();
+    for (line_index, line) in bits.chunks(8 * BYTES_PER_LINE).enumerate() {
+        let address = start + (line_index * 8 * BYTES_PER_LINE) as i128;
+        let sign = if address < 0 { '-' } else { '+' };
+        write!(text, "{}{:#018x}:", sign, address.unsigned_abs()).unwrap();
+
+        for byte in line.chunks(8) {
+            let value = byte.iter()
+                .enumerate()
+                .fold(0x00_u8, |value, (i, &bit)| value | ((bit as u8) << i));
+            write!(text, " {:02x}", value).unwrap();
+        }
+        text.push(' ');
+        for byte in line.chunks(8) {
+            text.push(' ');
+            text.extend(byte.iter().map(|&bit| if bit { '#' } else { '.' }));
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// Parse a dump into a memory holding its bits.
+pub fn parse_dump(text: &str) -> Result<Memory, ParseError> {
+    let mut memory = Memory::new();
+
+    for (line_index, line) in text.lines().enumerate() {
+        let error = |message: String| ParseError {
+            message,
+            line: line_index + 1,
+        };
+
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+
+        let (address, content) = line.split_once(':')
+            .ok_or_else(|| error("missing `:` after address".to_owned()))?;
+        let address = parse_address(address.trim())
+            .ok_or_else(|| error(format!("invalid address `{}`", address.trim())))?;
+
+        let mut hex_bits: Vec<bool> = Vec::new();
+        let mut view_bits: Vec<bool> = Vec::new();
+        for token in content.split_whitespace() {
+            if token.chars().all(|c| c == '.' || c == '#') {
+                view_bits.extend(token.chars().map(|c| c == '#'));
+            } else if token.len() == 2 && view_bits.is_empty() {
+                let value = u8::from_str_radix(token, 16)
+                    .map_err(|_| error(format!("invalid byte `{}`", token)))?;
+                hex_bits.extend((0..8).map(|i| value & (0x1 << i) != 0x00));
+            } else {
+                return Err(error(format!("unexpected `{}`", token)));
+            }
+        }
+
+        // the view also gives the length of a partial final byte
+        let bits = if view_bits.is_empty() {
+            hex_bits
+        } else {
+            let consistent = hex_bits.is_empty()
+                || (hex_bits.len() >= view_bits.len()
+                    && hex_bits.len() < view_bits.len() + 8
+                    && hex_bits[..view_bits.len()] == view_bits[..]
+                    && hex_bits[view_bits.len()..].iter().all(|&bit| !bit));
+            if !consistent {
+                return Err(error("hex and `.`/`#` view disagree".to_owned()));
+            }
+            view_bits
+        };
+
+        if address.checked_add(bits.len() as i128 - 1).is_none() {
+            return Err(error("line extends beyond the address space".to_owned()));
+        }
+        memory.write_range(address, &bits);
+    }
+    Ok(memory)
+}
+
+/// Parse an address or offset as hex if prefixed with `0x`, or otherwise as
+/// decimal, with an optional sign.
+pub(crate) fn parse_address(s: &str) -> Option<i128> {
+    let (negative, unsigned) = match s.as_bytes().first()? {
+        b'-' => (true, &s[1..]),
+        b'+' => (false, &s[1..]),
+        _ => (false, s),
+    };
+    let (digits, radix) = match unsigned.strip_prefix("0x") {
+        Some(hex) => (hex, 16),
+        None => (unsigned, 10),
+    };
+    // parsing would accept a second sign
+    if !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    let magnitude = u128::from_str_radix(digits, radix).ok()?;
+
+    if negative {
+        0_i128.checked_sub_unsigned(magnitude)
+    } else {
+        i128::try_from(magnitude).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GOLDEN: &str = "\
+-0x0000000000000040: 00 00 00 00 00 00 00 80  ........ ........ ........ ........ ........ ........ ........ .......#
++0x0000000000000000: 0b 00 00 00 00 00 00 00  ##.#.... ........ ........ ........ ........ ........ ........ ........
++0x0000000000000040: ff 01  ######## #
+";
+
+    fn golden_memory() -> Memory {
+        let mut memory = Memory::new();
+        memory.set_bit(-1, true);
+        memory.write_range(0, &[true, true, false, true]);
+        memory.fill_range(64, 73, true);
+        memory
+    }
+
+    #[test]
+    fn dumps_golden() {
+        assert_eq!(dump(&golden_memory(), -64, 73), GOLDEN);
+    }
+
+    #[test]
+    fn parses_golden() {
+        assert!(parse_dump(GOLDEN).unwrap() == golden_memory());
+    }
+
+    #[test]
+    fn parses_either_view() {
+        let text = "; comment\n\n3: ##.#\n-0x10: 05\n";
+        let memory = parse_dump(text).unwrap();
+        assert_eq!(memory.ones().collect::<Vec<_>>(), vec![-16, -14, 3, 4, 6]);
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        let line = |text| parse_dump(text).err().unwrap().line;
+        assert_eq!(line("0: #\n1 #"), 2);
+        assert_eq!(line("+-1: #"), 1);
+        assert_eq!(line("0: 01 ##"), 1);
+        assert_eq!(line("0: zz"), 1);
+        assert_eq!(line("0x7fffffffffffffffffffffffffffffff: ##"), 1);
+    }
+}
@@ -0,0 +1,207 @@
+use super::arena::{Arena, NodeId};
+use super::node::*;
+use super::page::{PAGE_BITS, PageBits};
+
+/// Iterator over the pages of a tree containing any set bits, in ascending
+/// address order, as their base address and bits.
+///
+/// Trees hold how bits differ from a memory's background, so these are the
+/// pages differing from it.
+///
+/// Double-ended, so also iterates in descending order.
+#[derive(Clone)]
+pub struct Pages<'a> {
+    arena: &'a Arena,
+    /// Nodes yet to be visited from the front, with the next on top.
+    front: Vec<&'a Node>,
+    /// Nodes yet to be visited from the back, with the next on top.
+    back: Vec<&'a Node>,
+    /// Row index of the last page reached from the front.
+    front_row_index: Option<i128>,
+    /// Row index of the last page reached from the back.
+    back_row_index: Option<i128>,
+}
+
+impl<'a> Pages<'a> {
+    pub fn new(arena: &'a Arena, root: NodeId) -> Self {
+        Pages {
+            arena,
+            front: vec![&arena[root]],
+            back: vec![&arena[root]],
+            front_row_index: None,
+            back_row_index: None,
+        }
+    }
+}
+
+impl<'a> Iterator for Pages<'a> {
+    type Item = (i128, &'a PageBits);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(node) = self.front.pop() {
+            match node {
+                Node::Page { row_index, bits } => {
+                    // stop upon meeting the back
+                    if self.back_row_index.map(|back| *row_index >= back).unwrap_or(false) {
+                        break;
+                    }
+                    self.front_row_index = Some(*row_index);
+
+                    if !bits.is_zeros() {
+                        return Some((base_address(*row_index, PageLevel), bits));
+                    }
+                }
+
+                Node::Branch { children, .. } => {
+                    let arena = self.arena;
+                    self.front.extend(children.iter().rev().flatten().map(|&child| &arena[child]));
+                }
+            }
+        }
+
+        self.front.clear();
+        None
+    }
+}
+
+impl<'a> DoubleEndedIterator for Pages<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        while let Some(node) = self.back.pop() {
+            match node {
+                Node::Page { row_index, bits } => {
+                    // stop upon meeting the front
+                    if self.front_row_index.map(|front| *row_index <= front).unwrap_or(false) {
+                        break;
+                    }
+                    self.back_row_index = Some(*row_index);
+
+                    if !bits.is_zeros() {
+                        return Some((base_address(*row_index, PageLevel), bits));
+                    }
+                }
+
+                Node::Branch { children, .. } => {
+                    let arena = self.arena;
+                    self.back.extend(children.iter().flatten().map(|&child| &arena[child]));
+                }
+            }
+        }
+
+        self.back.clear();
+        None
+    }
+}
+
+/// Remaining range of bits within a page.
+#[derive(Clone)]
+struct PageCursor<'a> {
+    base: i128,
+    bits: &'a PageBits,
+    /// Lowest bit index not yet passed.
+    low: usize,
+    /// One above the highest bit index not yet passed.
+    high: usize,
+}
+
+impl<'a> PageCursor<'a> {
+    fn new(base: i128, bits: &'a PageBits) -> Self {
+        PageCursor {
+            base,
+            bits,
+            low: 0,
+            high: PAGE_BITS,
+        }
+    }
+
+    /// Advance to and past the lowest remaining set bit.
+    fn next(&mut self) -> Option<i128> {
+        match self.bits.next_set(self.low) {
+            Some(i) if i < self.high => {
+                self.low = i + 1;
+                Some(self.base + i as i128)
+            }
+            _ => {
+                self.low = self.high;
+                None
+            }
+        }
+    }
+
+    /// Retreat to and past the highest remaining set bit.
+    fn next_back(&mut self) -> Option<i128> {
+        if self.high <= self.low {
+            return None;
+        }
+        match self.bits.prev_set(self.high - 1) {
+            Some(i) if i >= self.low => {
+                self.high = i;
+                Some(self.base + i as i128)
+            }
+            _ => {
+                self.high = self.low;
+                None
+            }
+        }
+    }
+}
+
+/// Iterator over the addresses of set bits of a tree, in ascending order,
+/// which are the bits differing from a memory's background.
+///
+/// Double-ended, so also iterates in descending order.
+#[derive(Clone)]
+pub struct Ones<'a> {
+    pages: Pages<'a>,
+    front: Option<PageCursor<'a>>,
+    back: Option<PageCursor<'a>>,
+}
+
+impl<'a> Ones<'a> {
+    pub fn new(arena: &'a Arena, root: NodeId) -> Self {
+        Ones {
+            pages: Pages::new(arena, root),
+            front: None,
+            back: None,
+        }
+    }
+}
+
+impl<'a> Iterator for Ones<'a> {
+    type Item = i128;
+
+    fn next(&mut self) -> Option<i128> {
+        loop {
+            if let Some(address) = self.front.as_mut().and_then(PageCursor::next) {
+                return Some(address);
+            }
+            match self.pages.next() {
+                Some((base, bits)) => {
+                    self.front = Some(PageCursor::new(base, bits));
+                }
+                None => {
+                    // any remaining bits are in the page the back is within
+                    return self.back.as_mut().and_then(PageCursor::next);
+                }
+            }
+        }
+    }
+}
+
+impl<'a> DoubleEndedIterator for Ones<'a> {
+    fn next_back(&mut self) -> Option<i128> {
+        loop {
+            if let Some(address) = self.back.as_mut().and_then(PageCursor::next_back) {
+                return Some(address);
+            }
+            match self.pages.next_back() {
+                Some((base, bits)) => {
+                    self.back = Some(PageCursor::new(base, bits));
+                }
+                None => {
+                    // any remaining bits are in the page the front is within
+                    return self.front.as_mut().and_then(PageCursor::next_back);
+                }
+            }
+        }
+    }
+}
@@ -1,38 +1,529 @@
 
 use std::{i128, usize};
+use std::hash::{Hash, Hasher};
 
-use self::node::Node;
+use self::arena::{Arena, NodeId};
+use self::background::Background;
+use self::node::{Node, page_segments, range_len};
+use self::page::{PAGE_BITS, PageBits};
+use self::twiddling::{get_word_bit, set_word_bit};
 
 pub(self) mod node;
 
 pub(self) mod twiddling;
 
+pub mod iter;
+
+pub mod cursor;
+
+pub mod persistent;
+
+pub mod snapshot;
+
+pub mod dump;
+
+pub mod background;
+
+pub mod store;
+
+pub mod dense;
+
+pub mod set;
+
+mod transform;
+
+mod arena;
+
+mod page;
+
 mod read;
 
 mod write;
 
+/// Bit at every 128-bit address, each no unless written, or with a
+/// background, each the background's unless written.
+///
+/// The tree holds only how bits differ from the background, so with a
+/// background, methods which enumerate set bits, such as `ones`, `pages`,
+/// `popcount` and `bounds`, enumerate the bits differing from it instead.
 #[derive(Clone)]
 pub struct Memory {
-    root: Box<Node>,
+    arena: Arena,
+    root: NodeId,
+    background: Background,
 }
 
 impl Memory {
     pub fn new() -> Self {
+        Memory::with_background(Background::zeros())
+    }
+
+    /// Memory holding a background at every address.
+    pub fn with_background(background: Background) -> Self {
+        let mut arena = Arena::new();
+        let root = arena.alloc(Node::page(0));
         Memory {
-            root: Box::new(Node::page(0))
+            arena,
+            root,
+            background,
         }
     }
 
+    pub fn background(&self) -> &Background {
+        &self.background
+    }
+
     pub fn get_bit(&self, address: i128) -> bool {
-        read::search_bit(self.root.as_ref(), address)
+        read::search_bit(&self.arena, self.root, address)
             .unwrap_or(false)
+            ^ self.background.get(address)
     }
 
     pub fn set_bit(&mut self, address: i128, bit: bool) {
-        write::insert_bit(&mut self.root, address, bit);
+        let bit = bit ^ self.background.get(address);
+        write::insert_bit(&mut self.arena, &mut self.root, address, bit);
+    }
+
+    /// Read-only cursor starting at some address.
+    pub fn cursor(&self, position: i128) -> cursor::Cursor<'_> {
+        cursor::Cursor::new(self, position)
+    }
+
+    /// Mutable cursor starting at some address.
+    pub fn cursor_mut(&mut self, position: i128) -> cursor::CursorMut<'_> {
+        cursor::CursorMut::new(self, position)
+    }
+
+    /// The address of the lowest bit differing from the background at or
+    /// above some address, if any, which without a background is the lowest
+    /// set bit.
+    pub fn next_set(&self, from: i128) -> Option<i128> {
+        read::search_next_set(&self.arena, self.root, from)
+    }
+
+    /// The address of the highest bit differing from the background at or
+    /// below some address, if any, which without a background is the highest
+    /// set bit.
+    pub fn prev_set(&self, from: i128) -> Option<i128> {
+        read::search_prev_set(&self.arena, self.root, from)
+    }
+
+    /// Read the bits of the address range `[start, end)`, in ascending order.
+    pub fn read_range(&self, start: i128, end: i128) -> Vec<bool> {
+        let mut vec = Vec::new();
+        for (segment_start, bit_index, len) in page_segments(start, range_len(start, end)) {
+            let background = (0..len as i128).map(|i| self.background.get(segment_start + i));
+            match read::search_page(&self.arena, self.root, segment_start) {
+                Some(bits) => {
+                    vec.extend((bit_index..(bit_index + len)).zip(background).map(|(i, bit)| bits.get(i) ^ bit));
+                }
+                None => {
+                    vec.extend(background);
+                }
+            }
+        }
+        vec
+    }
+
+    /// Read `64 * words.len()` bits starting at some address into a slice of
+    /// words, least significant bit first.
+    ///
+    /// Bits beyond the end of the address space are read as no.
+    pub fn read_words(&self, start: i128, words: &mut [u64]) {
+        for word in words.iter_mut() {
+            *word = 0x0;
+        }
+
+        let mut offset: usize = 0;
+        for (segment_start, bit_index, len) in page_segments(start, words.len() as u128 * 64) {
+            let bits = read::search_page(&self.arena, self.root, segment_start);
+            for i in 0..len {
+                let stored = bits.map(|bits| bits.get(bit_index + i)).unwrap_or(false);
+                if stored ^ self.background.get(segment_start + i as i128) {
+                    let word_bit = offset + i;
+                    words[word_bit / 64] |= 0x1 << (word_bit % 64);
+                }
+            }
+            offset += len;
+        }
+    }
+
+    /// Write a sequence of bits, starting at some address.
+    ///
+    /// Bits which would be beyond the end of the address space are ignored.
+    pub fn write_range(&mut self, start: i128, bits: &[bool]) {
+        let mut offset: usize = 0;
+        for (segment_start, bit_index, len) in page_segments(start, bits.len() as u128) {
+            // how each bit differs from the background
+            let segment: Vec<bool> = bits[offset..(offset + len)].iter()
+                .enumerate()
+                .map(|(i, &bit)| bit ^ self.background.get(segment_start + i as i128))
+                .collect();
+            offset += len;
+
+            if !segment.iter().any(|&bit| bit)
+                && read::search_page(&self.arena, self.root, segment_start).is_none() {
+                continue;
+            }
+
+            write::update_page(&mut self.arena, &mut self.root, segment_start, |page| {
+                page.update(|words| {
+                    for (i, &bit) in segment.iter().enumerate() {
+                        let page_bit = bit_index + i;
+                        set_word_bit(&mut words[page_bit / 8], (page_bit % 8) as u8, bit);
+                    }
+                })
+            });
+
+            if !segment.iter().any(|&bit| bit) {
+                write::prune_page(&mut self.arena, &mut self.root, segment_start);
+            }
+        }
+    }
+
+    /// Set every bit of the address range `[start, end)` to the same value.
+    pub fn fill_range(&mut self, start: i128, end: i128, bit: bool) {
+        for (segment_start, bit_index, len) in page_segments(start, range_len(start, end)) {
+            // how each bit of the page would differ from the background
+            let mut fill = self.background.page_words(segment_start - bit_index as i128);
+            if bit {
+                for word in fill.iter_mut() {
+                    *word = !*word;
+                }
+            }
+            let differs = (bit_index..(bit_index + len))
+                .any(|i| get_word_bit(fill[i / 8], (i % 8) as u8));
+
+            if !differs && read::search_page(&self.arena, self.root, segment_start).is_none() {
+                continue;
+            }
+
+            write::update_page(&mut self.arena, &mut self.root, segment_start, |page| {
+                if len == PAGE_BITS {
+                    // whole pages at once
+                    *page = PageBits::from_dense(&fill);
+                    return;
+                }
+
+                page.update(|words| {
+                    let end_bit = bit_index + len;
+                    let mut page_bit = bit_index;
+                    while page_bit < end_bit {
+                        if page_bit % 8 == 0 && page_bit + 8 <= end_bit {
+                            // whole words at once
+                            words[page_bit / 8] = fill[page_bit / 8];
+                            page_bit += 8;
+                        } else {
+                            let fill_bit = get_word_bit(fill[page_bit / 8], (page_bit % 8) as u8);
+                            set_word_bit(&mut words[page_bit / 8], (page_bit % 8) as u8, fill_bit);
+                            page_bit += 1;
+                        }
+                    }
+                })
+            });
+
+            if !differs {
+                write::prune_page(&mut self.arena, &mut self.root, segment_start);
+            }
+        }
+    }
+
+    /// Set every bit of the address range `[start, end)` to no.
+    pub fn clear_range(&mut self, start: i128, end: i128) {
+        self.fill_range(start, end, false);
     }
 
     pub fn tree_layers(&self) -> usize {
-        self.root.layers()
+        self.arena.layers(self.root)
+    }
+
+    /// Number of bits differing from the background, which without a
+    /// background are the set bits.
+    pub fn popcount(&self) -> u64 {
+        self.pages()
+            .map(|(_, bits)| bits.count_ones(0, PAGE_BITS))
+            .sum()
+    }
+
+    /// Number of bits differing from the background in the address range
+    /// `[start, end)`.
+    pub fn popcount_range(&self, start: i128, end: i128) -> u64 {
+        // offset of an address from a page's base, clamped to within the page
+        let clamp = |address: i128, base: i128| -> usize {
+            if address <= base {
+                0
+            } else {
+                (address.wrapping_sub(base) as u128).min(PAGE_BITS as u128) as usize
+            }
+        };
+
+        self.pages()
+            .take_while(|&(base, _)| base < end)
+            .map(|(base, bits)| bits.count_ones(clamp(start, base), clamp(end, base)))
+            .sum()
+    }
+
+    /// Iterate over the addresses of bits differing from the background,
+    /// which without a background are the set bits, in ascending order.
+    ///
+    /// Reverse the iterator for descending order.
+    pub fn ones(&self) -> iter::Ones<'_> {
+        iter::Ones::new(&self.arena, self.root)
+    }
+
+    /// Iterate over the pages containing any bits differing from the
+    /// background, in ascending order, as their base address and how their
+    /// bits differ from it.
+    pub fn pages(&self) -> iter::Pages<'_> {
+        iter::Pages::new(&self.arena, self.root)
+    }
+
+    /// The lowest and highest addresses of bits differing from the
+    /// background, if any.
+    pub fn bounds(&self) -> Option<(i128, i128)> {
+        let mut ones = self.ones();
+        let low = ones.next()?;
+        let high = ones.next_back().unwrap_or(low);
+        Some((low, high))
+    }
+
+    /// Set every bit to no, or with a background, to the background's,
+    /// releasing every node at once.
+    pub fn clear(&mut self) {
+        self.arena.clear();
+        self.root = self.arena.alloc(Node::page(0));
+    }
+
+    /// Number of pages currently allocated.
+    pub fn page_count(&self) -> usize {
+        self.arena.count().pages
     }
-}
\ No newline at end of file
+
+    /// Number of heap bytes allocated for the tree.
+    pub fn heap_bytes(&self) -> usize {
+        self.arena.heap_bytes()
+    }
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Memory::new()
+    }
+}
+
+/// Memories are equal if they hold the same bits, regardless of tree shape.
+impl PartialEq for Memory {
+    fn eq(&self, other: &Memory) -> bool {
+        self.background == other.background && self.pages().eq(other.pages())
+    }
+}
+
+impl Eq for Memory {}
+
+/// Consistent with equality, so independent of tree shape.
+impl Hash for Memory {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.background.hash(state);
+        for (base, bits) in self.pages() {
+            base.hash(state);
+            bits.hash(state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Backgrounds to check each operation over.
+    fn backgrounds() -> Vec<Background> {
+        vec![Background::zeros(), Background::new(&[true, false, false])]
+    }
+
+    /// Starts of ranges, unaligned and either side of page boundaries.
+    const STARTS: [i128; 6] = [-8200, -65, -1, 3, 8189, 20_000];
+
+    /// Lengths of ranges, within a word, a page, and crossing several.
+    const LENS: [usize; 6] = [1, 63, 64, 65, 8192, 20_001];
+
+    /// Memory with a pseudo-random pattern, written bit by bit.
+    fn patterned(background: Background) -> Memory {
+        let mut memory = Memory::with_background(background);
+        for address in -9000..30_000_i128 {
+            if (address * 7919).rem_euclid(13) < 5 {
+                memory.set_bit(address, true);
+            }
+        }
+        memory
+    }
+
+    #[test]
+    fn read_range_and_words_match_get_bit() {
+        for background in backgrounds() {
+            let memory = patterned(background);
+            for &start in &STARTS {
+                for &len in &LENS {
+                    let expected: Vec<bool> = (0..len as i128).map(|i| memory.get_bit(start + i)).collect();
+                    assert_eq!(memory.read_range(start, start + len as i128), expected);
+
+                    let mut words = vec![0x0; len.div_ceil(64)];
+                    memory.read_words(start, &mut words);
+                    for (i, &bit) in expected.iter().enumerate() {
+                        assert_eq!(words[i / 64] & (0x1 << (i % 64)) != 0, bit);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn write_and_fill_range_match_set_bit() {
+        for background in backgrounds() {
+            let patterned = patterned(background);
+            for &start in &STARTS {
+                for &len in &LENS {
+                    let bits: Vec<bool> = (0..len).map(|i| i % 3 == 0 || i % 5 == 0).collect();
+                    let mut expected = patterned.clone();
+                    for (i, &bit) in bits.iter().enumerate() {
+                        expected.set_bit(start + i as i128, bit);
+                    }
+                    let mut memory = patterned.clone();
+                    memory.write_range(start, &bits);
+                    assert!(memory == expected);
+
+                    for &bit in &[false, true] {
+                        let end = start + len as i128;
+                        let mut expected = patterned.clone();
+                        for address in start..end {
+                            expected.set_bit(address, bit);
+                        }
+                        let mut memory = patterned.clone();
+                        memory.fill_range(start, end, bit);
+                        assert!(memory == expected);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bulk_operations_stop_at_end_of_address_space() {
+        for background in backgrounds() {
+            let mut memory = Memory::with_background(background);
+            memory.write_range(i128::MAX - 3, &[true; 10]);
+            assert_eq!(memory.read_range(i128::MAX - 3, i128::MAX), vec![true; 3]);
+            assert!(memory.get_bit(i128::MAX));
+
+            // bits beyond the end read as no, even over a background
+            let mut words = [!0x0; 2];
+            memory.read_words(i128::MAX - 3, &mut words);
+            assert_eq!(words, [0xF, 0x0]);
+
+            memory.fill_range(i128::MAX - 100, i128::MAX, false);
+            assert_eq!(memory.read_range(i128::MAX - 100, i128::MAX), vec![false; 100]);
+            assert!(memory.get_bit(i128::MAX));
+        }
+    }
+
+    #[test]
+    fn next_and_prev_set_match_oracle() {
+        use std::collections::BTreeSet;
+
+        // page and branch boundaries, pages holding 8192 bits, and branches
+        // 128 children
+        let page = 8192_i128;
+        let branch = page * 128;
+        let mut addresses: Vec<i128> = vec![i128::MIN, i128::MIN + 1, i128::MAX - 1, i128::MAX, 0, -1];
+        for &boundary in &[page, branch, branch * 128, -page, -branch, 5 * page, -3 * branch] {
+            addresses.extend(&[boundary - 1, boundary, boundary + 1]);
+        }
+
+        let empty = Memory::new();
+        for &from in &addresses {
+            assert_eq!(empty.next_set(from), None);
+            assert_eq!(empty.prev_set(from), None);
+        }
+
+        // every other candidate, either way, so that some searches start on
+        // set bits and some between them
+        for skip in 0..2 {
+            let mut memory = Memory::new();
+            let mut oracle = BTreeSet::new();
+            for &address in addresses.iter().skip(skip).step_by(2) {
+                memory.set_bit(address, true);
+                oracle.insert(address);
+            }
+            for &from in &addresses {
+                assert_eq!(memory.next_set(from), oracle.range(from..).next().copied(), "next from {}", from);
+                assert_eq!(memory.prev_set(from), oracle.range(..=from).next_back().copied(), "prev from {}", from);
+            }
+        }
+    }
+
+    #[test]
+    fn popcount_range_matches_oracle() {
+        let mut memory = patterned(Background::zeros());
+        memory.set_bit(i128::MIN, true);
+        memory.set_bit(i128::MAX, true);
+        let count = |start: i128, end: i128| (start..end).filter(|&a| memory.get_bit(a)).count() as u64;
+
+        for &start in &STARTS {
+            for &len in &LENS {
+                let end = start + len as i128;
+                assert_eq!(memory.popcount_range(start, end), count(start, end), "[{}, {})", start, end);
+            }
+        }
+        assert_eq!(memory.popcount_range(5, 5), 0);
+        // the range excludes its end
+        assert_eq!(memory.popcount(), count(-9000, 30_000) + 2);
+        assert_eq!(memory.popcount_range(i128::MIN, i128::MAX), memory.popcount() - 1);
+        assert_eq!(memory.popcount_range(i128::MAX - 1, i128::MAX), 0);
+        assert_eq!(memory.popcount_range(i128::MIN, i128::MIN + 1), 1);
+    }
+
+    fn hash_of(memory: &Memory) -> u64 {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        memory.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn equal_content_is_equal_whatever_the_writes() {
+        // pages of each encoding: sparse, dense and all ones
+        let ranges = [(0, 5), (8192, 8192 + 300), (-8192, 0)];
+
+        let mut forward = Memory::new();
+        for &(start, end) in &ranges {
+            for address in start..end {
+                forward.set_bit(address, true);
+            }
+        }
+
+        let mut backward = Memory::new();
+        backward.set_bit(1 << 100, true);
+        for &(start, end) in ranges.iter().rev() {
+            for address in (start..end).rev() {
+                backward.set_bit(address, true);
+            }
+        }
+        backward.set_bit(1 << 100, false);
+
+        let mut bulk = Memory::new();
+        bulk.fill_range(-8192, 8192 * 2, true);
+        bulk.fill_range(5, 8192, false);
+        bulk.write_range(8192 + 300, &[false; 8192]);
+
+        for memory in &[&backward, &bulk] {
+            assert!(**memory == forward);
+            assert_eq!(hash_of(memory), hash_of(&forward));
+        }
+
+        // and which differ only by one bit are not, for each encoding
+        for &(start, _) in &ranges {
+            let mut other = bulk.clone();
+            other.set_bit(start + 1, false);
+            assert!(other != forward);
+            assert_ne!(hash_of(&other), hash_of(&forward));
+        }
+    }
+}
@@ -1,8 +1,9 @@
 
 use std::{i128, usize};
-use std::mem::{size_of, transmute};
-use std::ptr;
-use super::twiddling::*;
+use std::mem::size_of;
+
+use super::arena::NodeId;
+use super::page::PageBits;
 
 pub const PAGE_SIZE: usize = 0x400;
 pub const BRANCH_FACTOR: usize = PAGE_SIZE / size_of::<usize>();
@@ -11,31 +12,27 @@ pub const BRANCH_FACTOR: usize = PAGE_SIZE / size_of::<usize>();
 pub enum Node {
     Page {
         row_index: i128,
-        bits: [u8; PAGE_SIZE],
+        bits: PageBits,
     },
     Branch {
         level: BranchLevel,
         row_index: i128,
-        children: [Option<Box<Node>>; BRANCH_FACTOR],
+        children: Box<[Option<NodeId>; BRANCH_FACTOR]>,
     },
 }
 
-pub fn new_children() -> [Option<Box<Node>>; BRANCH_FACTOR] {
-    unsafe {
-        let equivalent: [*mut Node; BRANCH_FACTOR] = [ptr::null_mut(); BRANCH_FACTOR];
-
-        transmute::<
-            [*mut Node; BRANCH_FACTOR],
-            [Option<Box<Node>>; BRANCH_FACTOR]
-        >(equivalent)
-    }
+/// Running count of allocated nodes.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct NodeCount {
+    pub pages: usize,
+    pub branches: usize,
 }
 
 impl Node {
     pub fn page(row_index: i128) -> Self {
         Node::Page {
             row_index,
-            bits: [0x00; PAGE_SIZE],
+            bits: PageBits::Zeros,
         }
     }
 
@@ -64,40 +61,35 @@ impl Node {
             &Node::Branch { level: BranchLevel(l), .. } => BranchLevel(l + 1),
         }
     }
-
-    pub fn layers(&self) -> usize {
-        match self {
-            &Node::Page { .. } => 1,
-            &Node::Branch {
-                ref children,
-                ..
-            } => {
-                (&children[..]).iter()
-                    .filter_map(Option::as_ref)
-                    .map(Box::as_ref)
-                    .map(|node| node.layers())
-                    .max()
-                    .unwrap_or(0) + 1
-            }
-        }
-    }
 }
 
+/// Addresses are biased into the unsigned range before being split into
+/// rows, so that negative and positive addresses share ancestors, and the
+/// tree can grow to span the whole address space.
+pub const ADDRESS_BIAS: u128 = 0x1 << 127;
+
 pub trait TreeLevel {
     type Parent: TreeLevel;
 
-    fn scale_factor(&self) -> i128;
+    /// Log base 2 of the number of bits spanned by a node at this level.
+    fn scale_log2(&self) -> u32;
 
     fn parent(&self) -> Self::Parent;
 }
 
+/// Log base 2 of the number of bits in a page.
+pub const PAGE_LOG2: u32 = (8 * PAGE_SIZE).trailing_zeros();
+
+/// Log base 2 of the branch factor.
+pub const BRANCH_LOG2: u32 = BRANCH_FACTOR.trailing_zeros();
+
 #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
 pub struct BitLevel;
 impl TreeLevel for BitLevel {
     type Parent = WordLevel;
 
-    fn scale_factor(&self) -> i128 {
-        1
+    fn scale_log2(&self) -> u32 {
+        0
     }
 
     fn parent(&self) -> Self::Parent {
@@ -110,8 +102,8 @@ pub struct WordLevel;
 impl TreeLevel for WordLevel {
     type Parent = PageLevel;
 
-    fn scale_factor(&self) -> i128 {
-        8
+    fn scale_log2(&self) -> u32 {
+        3
     }
 
     fn parent(&self) -> Self::Parent {
@@ -124,8 +116,8 @@ pub struct PageLevel;
 impl TreeLevel for PageLevel {
     type Parent = BranchLevel;
 
-    fn scale_factor(&self) -> i128 {
-        8 * PAGE_SIZE as i128
+    fn scale_log2(&self) -> u32 {
+        PAGE_LOG2
     }
 
     fn parent(&self) -> Self::Parent {
@@ -138,8 +130,8 @@ pub struct BranchLevel(pub u32);
 impl TreeLevel for BranchLevel {
     type Parent = BranchLevel;
 
-    fn scale_factor(&self) -> i128 {
-        8 * PAGE_SIZE as i128 * (BRANCH_FACTOR as i128).pow(self.0 + 1)
+    fn scale_log2(&self) -> u32 {
+        PAGE_LOG2 + BRANCH_LOG2 * (self.0 + 1)
     }
 
     fn parent(&self) -> Self::Parent {
@@ -152,8 +144,8 @@ pub struct ChildOfBranchLevel(pub BranchLevel);
 impl TreeLevel for ChildOfBranchLevel {
     type Parent = BranchLevel;
 
-    fn scale_factor(&self) -> i128 {
-        8 * PAGE_SIZE as i128 * (BRANCH_FACTOR as i128).pow((self.0).0)
+    fn scale_log2(&self) -> u32 {
+        PAGE_LOG2 + BRANCH_LOG2 * (self.0).0
     }
 
     fn parent(&self) -> Self::Parent {
@@ -161,13 +153,57 @@ impl TreeLevel for ChildOfBranchLevel {
     }
 }
 
+/// Address, biased into the unsigned range.
+pub fn biased(address: i128) -> u128 {
+    (address as u128) ^ ADDRESS_BIAS
+}
+
 pub fn row_index<T: TreeLevel>(address: i128, level: T) -> i128 {
-    floor_div(address, level.scale_factor())
+    biased(address).checked_shr(level.scale_log2()).unwrap_or(0) as i128
 }
 
 pub fn child_index<T: TreeLevel>(address: i128, level: T) -> usize {
-    floor_div(
-        floor_rem(address, level.parent().scale_factor() as i128),
-        level.scale_factor() as i128,
-    ) as usize
-}
\ No newline at end of file
+    let shift = level.scale_log2();
+    let mask: u128 = (0x1 << (level.parent().scale_log2() - shift)) - 1;
+
+    (biased(address).checked_shr(shift).unwrap_or(0) & mask) as usize
+}
+
+/// Lowest address within the node at some row index of some level.
+pub fn base_address<T: TreeLevel>(row_index: i128, level: T) -> i128 {
+    ((row_index as u128).checked_shl(level.scale_log2()).unwrap_or(0) ^ ADDRESS_BIAS) as i128
+}
+
+/// Split the `len` addresses starting at `start` into segments which each lie
+/// within a single page, as the segment's start address, the index of that bit
+/// within its page, and the segment's length.
+///
+/// Stops at the end of the address space.
+pub fn page_segments(start: i128, len: u128) -> impl Iterator<Item=(i128, usize, usize)> {
+    const PAGE_BITS: u128 = 0x1 << PAGE_LOG2;
+
+    let until_end = (i128::MAX as u128).wrapping_sub(start as u128).checked_add(1);
+    let mut remaining = until_end.map(|until_end| len.min(until_end)).unwrap_or(len);
+    let mut curr = start;
+    std::iter::from_fn(move || {
+        if remaining == 0 {
+            return None;
+        }
+        let bit_index = biased(curr) % PAGE_BITS;
+        let len = (PAGE_BITS - bit_index).min(remaining);
+
+        let segment = (curr, bit_index as usize, len as usize);
+        curr = curr.wrapping_add(len as i128);
+        remaining -= len;
+        Some(segment)
+    })
+}
+
+/// Number of addresses in the range `[start, end)`.
+pub fn range_len(start: i128, end: i128) -> u128 {
+    if end > start {
+        (end as u128).wrapping_sub(start as u128)
+    } else {
+        0
+    }
+}
@@ -0,0 +1,351 @@
+use super::node::PAGE_SIZE;
+use super::twiddling::*;
+
+/// Number of bits in a page.
+pub const PAGE_BITS: usize = 8 * PAGE_SIZE;
+
+/// Most set bits a page holds in sparse form.
+pub const SPARSE_MAX: usize = PAGE_SIZE / 8;
+
+/// Bits of a page, encoded according to how many are set.
+///
+/// The encoding is always the one dictated by the number of set bits, so equal
+/// content is equally encoded, and equality and hashing can be derived.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub enum PageBits {
+    /// No bits set.
+    Zeros,
+    /// Every bit set.
+    Ones,
+    /// Up to `SPARSE_MAX` bits set, as their sorted indices.
+    Sparse(Vec<u16>),
+    /// Any other number of bits set.
+    Dense {
+        count: usize,
+        bits: Box<[u8; PAGE_SIZE]>,
+    },
+}
+
+impl PageBits {
+    /// Encode a page from its bits as words.
+    pub fn from_dense(bits: &[u8; PAGE_SIZE]) -> Self {
+        let count: usize = bits.iter()
+            .map(|word| word.count_ones() as usize)
+            .sum();
+
+        if count == 0 {
+            PageBits::Zeros
+        } else if count == PAGE_BITS {
+            PageBits::Ones
+        } else if count <= SPARSE_MAX {
+            PageBits::Sparse((0..PAGE_BITS)
+                .filter(|&i| get_word_bit(bits[i / 8], (i % 8) as u8))
+                .map(|i| i as u16)
+                .collect())
+        } else {
+            PageBits::Dense {
+                count,
+                bits: Box::new(*bits),
+            }
+        }
+    }
+
+    /// Decode a page into its bits as words.
+    pub fn to_dense(&self) -> [u8; PAGE_SIZE] {
+        match self {
+            PageBits::Zeros => [0x00; PAGE_SIZE],
+            PageBits::Ones => [0xFF; PAGE_SIZE],
+            PageBits::Sparse(indices) => {
+                let mut bits = [0x00; PAGE_SIZE];
+                for &i in indices {
+                    let i = i as usize;
+                    set_word_bit(&mut bits[i / 8], (i % 8) as u8, true);
+                }
+                bits
+            }
+            PageBits::Dense { bits, .. } => **bits,
+        }
+    }
+
+    pub fn is_zeros(&self) -> bool {
+        *self == PageBits::Zeros
+    }
+
+    pub fn get(&self, i: usize) -> bool {
+        match self {
+            PageBits::Zeros => false,
+            PageBits::Ones => true,
+            PageBits::Sparse(indices) => indices.binary_search(&(i as u16)).is_ok(),
+            PageBits::Dense { bits, .. } => get_word_bit(bits[i / 8], (i % 8) as u8),
+        }
+    }
+
+    pub fn set(&mut self, i: usize, bit: bool) {
+        match self {
+            PageBits::Zeros => {
+                if bit {
+                    *self = PageBits::Sparse(vec![i as u16]);
+                }
+            }
+
+            PageBits::Ones => {
+                if !bit {
+                    let mut bits = Box::new([0xFF; PAGE_SIZE]);
+                    set_word_bit(&mut bits[i / 8], (i % 8) as u8, false);
+                    *self = PageBits::Dense {
+                        count: PAGE_BITS - 1,
+                        bits,
+                    };
+                }
+            }
+
+            PageBits::Sparse(indices) => {
+                match (indices.binary_search(&(i as u16)), bit) {
+                    (Err(position), true) => indices.insert(position, i as u16),
+                    (Ok(position), false) => {
+                        indices.remove(position);
+                    }
+                    _ => (),
+                }
+
+                if indices.is_empty() {
+                    *self = PageBits::Zeros;
+                } else if indices.len() > SPARSE_MAX {
+                    *self = PageBits::from_dense(&self.to_dense());
+                }
+            }
+
+            PageBits::Dense { count, bits } => {
+                let word: &mut u8 = &mut bits[i / 8];
+                if get_word_bit(*word, (i % 8) as u8) != bit {
+                    set_word_bit(word, (i % 8) as u8, bit);
+                    if bit {
+                        *count += 1;
+                    } else {
+                        *count -= 1;
+                    }
+                }
+
+                if *count == PAGE_BITS {
+                    *self = PageBits::Ones;
+                } else if *count <= SPARSE_MAX {
+                    *self = PageBits::from_dense(bits);
+                }
+            }
+        }
+    }
+
+    /// Modify the bits as words, then re-encode them.
+    pub fn update<F: FnOnce(&mut [u8; PAGE_SIZE])>(&mut self, modify: F) {
+        let mut bits = self.to_dense();
+        modify(&mut bits);
+        *self = PageBits::from_dense(&bits);
+    }
+
+    /// Number of set bits with indices in `[low, high)`.
+    pub fn count_ones(&self, low: usize, high: usize) -> u64 {
+        if low >= high {
+            return 0;
+        }
+        match self {
+            PageBits::Zeros => 0,
+            PageBits::Ones => (high - low) as u64,
+            PageBits::Sparse(indices) => {
+                let start = indices.partition_point(|&i| (i as usize) < low);
+                let end = indices.partition_point(|&i| (i as usize) < high);
+                (end - start) as u64
+            }
+            PageBits::Dense { bits, .. } => {
+                let mut count: u64 = 0;
+                let mut i = low;
+                while i < high {
+                    if i.is_multiple_of(8) && i + 8 <= high {
+                        // whole words at once
+                        count += bits[i / 8].count_ones() as u64;
+                        i += 8;
+                    } else {
+                        count += get_word_bit(bits[i / 8], (i % 8) as u8) as u64;
+                        i += 1;
+                    }
+                }
+                count
+            }
+        }
+    }
+
+    /// Index of the lowest set bit at or above some index.
+    pub fn next_set(&self, start: usize) -> Option<usize> {
+        if start >= PAGE_BITS {
+            return None;
+        }
+        match self {
+            PageBits::Zeros => None,
+            PageBits::Ones => Some(start),
+            PageBits::Sparse(indices) => {
+                let position = indices.partition_point(|&i| (i as usize) < start);
+                indices.get(position).map(|&i| i as usize)
+            }
+            PageBits::Dense { bits, .. } => {
+                // mask off the bits of the first word below the start
+                let first = bits[start / 8] & (0xFF << (start % 8));
+                if first != 0x00 {
+                    return Some(start - start % 8 + first.trailing_zeros() as usize);
+                }
+
+                ((start / 8 + 1)..PAGE_SIZE)
+                    .find(|&word_index| bits[word_index] != 0x00)
+                    .map(|word_index| word_index * 8 + bits[word_index].trailing_zeros() as usize)
+            }
+        }
+    }
+
+    /// Index of the highest set bit at or below some index.
+    pub fn prev_set(&self, end: usize) -> Option<usize> {
+        let end = end.min(PAGE_BITS - 1);
+        match self {
+            PageBits::Zeros => None,
+            PageBits::Ones => Some(end),
+            PageBits::Sparse(indices) => {
+                let position = indices.partition_point(|&i| (i as usize) <= end);
+                position.checked_sub(1).map(|position| indices[position] as usize)
+            }
+            PageBits::Dense { bits, .. } => {
+                // mask off the bits of the last word above the end
+                let last = bits[end / 8] & (0xFF >> (7 - end % 8));
+                if last != 0x00 {
+                    return Some(end - end % 8 + 7 - last.leading_zeros() as usize);
+                }
+
+                (0..(end / 8)).rev()
+                    .find(|&word_index| bits[word_index] != 0x00)
+                    .map(|word_index| word_index * 8 + 7 - bits[word_index].leading_zeros() as usize)
+            }
+        }
+    }
+
+    /// Number of heap bytes allocated for the bits.
+    pub fn heap_bytes(&self) -> usize {
+        match self {
+            PageBits::Zeros | PageBits::Ones => 0,
+            PageBits::Sparse(indices) => indices.capacity() * 2,
+            PageBits::Dense { .. } => PAGE_SIZE,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    fn is_sparse(page: &PageBits) -> bool {
+        matches!(page, PageBits::Sparse(_))
+    }
+
+    fn is_dense(page: &PageBits) -> bool {
+        matches!(page, PageBits::Dense { .. })
+    }
+
+    /// Distinct indices spread across the page, in no particular order.
+    fn scattered(n: usize) -> impl Iterator<Item=usize> {
+        (0..n).map(|i| i * 4099 % PAGE_BITS)
+    }
+
+    fn hash_of(page: &PageBits) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        page.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn switches_encoding_at_sparse_max() {
+        let mut page = PageBits::Zeros;
+        for (count, i) in scattered(SPARSE_MAX + 1).enumerate() {
+            assert_eq!(is_sparse(&page), count > 0);
+            page.set(i, true);
+        }
+        assert!(is_dense(&page));
+
+        // and back again
+        page.set(scattered(1).next().unwrap(), false);
+        assert!(is_sparse(&page));
+        page.set(scattered(1).next().unwrap(), true);
+        assert!(is_dense(&page));
+
+        // as when updated in bulk, here clearing bits 0 and 6
+        page.update(|words| words[0] = 0x00);
+        assert!(is_sparse(&page));
+        assert_eq!(page.count_ones(0, PAGE_BITS), SPARSE_MAX as u64 - 1);
+    }
+
+    #[test]
+    fn filling_and_clearing_canonicalize() {
+        let mut page = PageBits::Zeros;
+        for i in scattered(PAGE_BITS) {
+            assert!(page != PageBits::Ones);
+            page.set(i, true);
+        }
+        assert_eq!(page, PageBits::Ones);
+
+        page.set(5, false);
+        assert!(is_dense(&page));
+        page.set(5, true);
+        assert_eq!(page, PageBits::Ones);
+
+        for i in scattered(PAGE_BITS) {
+            assert!(page != PageBits::Zeros);
+            page.set(i, false);
+        }
+        assert_eq!(page, PageBits::Zeros);
+
+        page.update(|words| *words = [0xFF; PAGE_SIZE]);
+        assert_eq!(page, PageBits::Ones);
+        page.update(|words| *words = [0x00; PAGE_SIZE]);
+        assert_eq!(page, PageBits::Zeros);
+        assert_eq!(PageBits::from_dense(&[0xFF; PAGE_SIZE]), PageBits::Ones);
+        assert_eq!(PageBits::from_dense(&[0x00; PAGE_SIZE]), PageBits::Zeros);
+    }
+
+    #[test]
+    fn equal_bits_are_equal_whatever_the_updates() {
+        for &n in &[1, SPARSE_MAX, SPARSE_MAX + 1, PAGE_BITS - 1] {
+            let target: Vec<usize> = scattered(n).collect();
+
+            let mut ascending = PageBits::Zeros;
+            let mut sorted = target.clone();
+            sorted.sort_unstable();
+            for &i in &sorted {
+                ascending.set(i, true);
+            }
+
+            // by way of a full page
+            let mut descending = PageBits::Ones;
+            for i in (0..PAGE_BITS).rev() {
+                descending.set(i, sorted.binary_search(&i).is_ok());
+            }
+
+            // by way of extra bits, set and cleared again
+            let mut detour = PageBits::Zeros;
+            for &i in target.iter().rev() {
+                detour.set(i, true);
+                detour.set(PAGE_BITS - 1 - i, true);
+            }
+            for i in 0..PAGE_BITS {
+                if sorted.binary_search(&i).is_err() {
+                    detour.set(i, false);
+                }
+            }
+
+            let mut bulk = PageBits::Zeros;
+            bulk.update(|words| *words = ascending.to_dense());
+
+            for page in &[&descending, &detour, &bulk] {
+                assert_eq!(*page, &ascending, "{} bits", n);
+                assert_eq!(hash_of(page), hash_of(&ascending), "{} bits", n);
+            }
+            assert!((0..PAGE_BITS).all(|i| ascending.get(i) == sorted.binary_search(&i).is_ok()));
+        }
+    }
+}
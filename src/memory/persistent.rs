@@ -0,0 +1,187 @@
+use super::arena::{Arena, NodeId};
+use super::background::Background;
+use super::iter::Pages;
+use super::node::Node;
+use super::{Memory, read, write};
+
+use std::cell::RefCell;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+/// Memory with structurally shared nodes, so that cloning is O(1), and a
+/// write copies only the path from the root to the page written.
+///
+/// Clones share a single arena, with the same tree operations as `Memory`,
+/// and nodes are freed once no clone refers to them.
+///
+/// Suited to keeping many historical generations.
+pub struct PersistentMemory {
+    arena: Rc<RefCell<Arena>>,
+    root: NodeId,
+}
+
+impl PersistentMemory {
+    pub fn new() -> Self {
+        let mut arena = Arena::new();
+        let root = arena.alloc(Node::page(0));
+        PersistentMemory {
+            arena: Rc::new(RefCell::new(arena)),
+            root,
+        }
+    }
+
+    pub fn get_bit(&self, address: i128) -> bool {
+        read::search_bit(&self.arena.borrow(), self.root, address)
+            .unwrap_or(false)
+    }
+
+    pub fn set_bit(&mut self, address: i128, bit: bool) {
+        write::insert_bit(&mut self.arena.borrow_mut(), &mut self.root, address, bit);
+    }
+
+    /// Whether two memories share their entire tree, in which case they hold
+    /// the same bits.
+    pub fn ptr_eq(&self, other: &PersistentMemory) -> bool {
+        Rc::ptr_eq(&self.arena, &other.arena) && self.root == other.root
+    }
+
+    /// Iterate over the addresses of set bits, in ascending order.
+    pub fn ones(&self) -> impl Iterator<Item=i128> + '_ {
+        let mut from: Option<i128> = Some(i128::MIN);
+        std::iter::from_fn(move || {
+            let address = read::search_next_set(&self.arena.borrow(), self.root, from?)?;
+            from = address.checked_add(1);
+            Some(address)
+        })
+    }
+}
+
+impl Default for PersistentMemory {
+    fn default() -> Self {
+        PersistentMemory::new()
+    }
+}
+
+impl Clone for PersistentMemory {
+    fn clone(&self) -> Self {
+        self.arena.borrow_mut().retain(self.root);
+        PersistentMemory {
+            arena: Rc::clone(&self.arena),
+            root: self.root,
+        }
+    }
+}
+
+impl Drop for PersistentMemory {
+    fn drop(&mut self) {
+        self.arena.borrow_mut().release(self.root);
+    }
+}
+
+/// Memories are equal if they hold the same bits, regardless of tree shape.
+impl PartialEq for PersistentMemory {
+    fn eq(&self, other: &PersistentMemory) -> bool {
+        self.ptr_eq(other) || {
+            let (arena, other_arena) = (self.arena.borrow(), other.arena.borrow());
+            Pages::new(&arena, self.root).eq(Pages::new(&other_arena, other.root))
+        }
+    }
+}
+
+impl Eq for PersistentMemory {}
+
+/// Consistent with equality, and with hashing a `Memory` of the same bits.
+impl Hash for PersistentMemory {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let arena = self.arena.borrow();
+        Background::zeros().hash(state);
+        for (base, bits) in Pages::new(&arena, self.root) {
+            base.hash(state);
+            bits.hash(state);
+        }
+    }
+}
+
+/// Panics if the memory has a background, which persistent memory lacks.
+impl From<&Memory> for PersistentMemory {
+    fn from(memory: &Memory) -> Self {
+        assert!(memory.background().is_zeros(), "persistent memory has no background");
+        let mut persistent = PersistentMemory::new();
+        {
+            let mut arena = persistent.arena.borrow_mut();
+            for (base, bits) in memory.pages() {
+                write::update_page(&mut arena, &mut persistent.root, base, |page| *page = bits.clone());
+            }
+        }
+        persistent
+    }
+}
+
+impl From<&PersistentMemory> for Memory {
+    fn from(persistent: &PersistentMemory) -> Self {
+        let mut memory = Memory::new();
+        let arena = persistent.arena.borrow();
+        for (base, bits) in Pages::new(&arena, persistent.root) {
+            write::update_page(&mut memory.arena, &mut memory.root, base, |page| *page = bits.clone());
+        }
+        memory
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clones_are_independent() {
+        let mut a = PersistentMemory::new();
+        for &address in &[-100_000, -1, 0, 5, 70_000, i128::MAX] {
+            a.set_bit(address, true);
+        }
+        let before: Vec<i128> = a.ones().collect();
+
+        let mut b = a.clone();
+        assert!(b.ptr_eq(&a));
+        b.set_bit(5, false);
+        b.set_bit(3, true);
+        assert!(!b.ptr_eq(&a));
+
+        assert_eq!(a.ones().collect::<Vec<_>>(), before);
+        assert_eq!(b.ones().collect::<Vec<_>>(), vec![-100_000, -1, 0, 3, 70_000, i128::MAX]);
+        assert!(a != b);
+
+        b.set_bit(3, false);
+        b.set_bit(5, true);
+        assert!(a == b);
+    }
+
+    #[test]
+    fn writes_copy_only_their_path() {
+        let mut a = PersistentMemory::new();
+        for i in 0..64 {
+            a.set_bit(i * 0x10000, true);
+        }
+        let count = a.arena.borrow().count();
+
+        let mut b = a.clone();
+        b.set_bit(1, true);
+        assert_eq!(a.arena.borrow().count().pages, count.pages + 1);
+
+        // the copies are freed along with the last memory using them
+        drop(b);
+        assert_eq!(a.arena.borrow().count(), count);
+        drop(a.clone());
+        assert_eq!(a.arena.borrow().count(), count);
+    }
+
+    #[test]
+    fn converts_to_and_from_memory() {
+        let mut memory = Memory::new();
+        memory.fill_range(-3000, 3000, true);
+        memory.set_bit(1 << 100, true);
+
+        let persistent = PersistentMemory::from(&memory);
+        assert!(persistent.ones().eq(memory.ones()));
+        assert!(Memory::from(&persistent) == memory);
+    }
+}
@@ -1,9 +1,16 @@
 
+use super::arena::{Arena, NodeId};
 use super::node::*;
-use super::twiddling::*;
+use super::page::{PAGE_BITS, PageBits};
 
-pub fn search_bit(root: &Node, address: i128) -> Option<bool> {
-    let mut curr: &Node = root;
+pub fn search_bit(arena: &Arena, root: NodeId, address: i128) -> Option<bool> {
+    search_page(arena, root, address)
+        .map(|bits| bits.get(page_bit_index(address)))
+}
+
+/// Find the bits of the page containing an address, if it exists.
+pub fn search_page(arena: &Arena, root: NodeId, address: i128) -> Option<&PageBits> {
+    let mut curr: &Node = &arena[root];
     loop {
 
         if curr.row_index() == curr.row_index_of_address(address) {
@@ -13,13 +20,7 @@ pub fn search_bit(root: &Node, address: i128) -> Option<bool> {
                     ref bits,
                     ..
                 } => {
-                    let word_index = child_index(address, WordLevel);
-                    let bit_index = child_index(address, BitLevel) as u8;
-
-                    let word = bits[word_index];
-                    let bit = get_word_bit(word, bit_index);
-
-                    return Some(bit);
+                    return Some(bits);
                 }
 
                 &Node::Branch {
@@ -29,8 +30,8 @@ pub fn search_bit(root: &Node, address: i128) -> Option<bool> {
                 } => {
                     let i = child_index(address, ChildOfBranchLevel(level));
 
-                    if let &Some(ref node) = &children[i] {
-                        curr = Box::as_ref(node);
+                    if let Some(child) = children[i] {
+                        curr = &arena[child];
                     } else {
                         return None;
                     }
@@ -42,4 +43,64 @@ pub fn search_bit(root: &Node, address: i128) -> Option<bool> {
         }
 
     }
-}
\ No newline at end of file
+}
+
+/// Find the lowest set bit at or above an address, skipping subtrees which
+/// lie entirely below it.
+pub fn search_next_set(arena: &Arena, id: NodeId, from: i128) -> Option<i128> {
+    let node: &Node = &arena[id];
+    let row_index = node.row_index();
+    let from_row_index = node.row_index_of_address(from);
+    if row_index < from_row_index {
+        return None;
+    }
+    let within = row_index == from_row_index;
+
+    match node {
+        Node::Page { bits, .. } => {
+            let start = if within { page_bit_index(from) } else { 0 };
+            bits.next_set(start)
+                .map(|i| base_address(row_index, PageLevel) + i as i128)
+        }
+
+        Node::Branch { level, children, .. } => {
+            let start = if within { child_index(from, ChildOfBranchLevel(*level)) } else { 0 };
+            children[start..].iter()
+                .flatten()
+                .find_map(|&child| search_next_set(arena, child, from))
+        }
+    }
+}
+
+/// Find the highest set bit at or below an address, skipping subtrees which
+/// lie entirely above it.
+pub fn search_prev_set(arena: &Arena, id: NodeId, from: i128) -> Option<i128> {
+    let node: &Node = &arena[id];
+    let row_index = node.row_index();
+    let from_row_index = node.row_index_of_address(from);
+    if row_index > from_row_index {
+        return None;
+    }
+    let within = row_index == from_row_index;
+
+    match node {
+        Node::Page { bits, .. } => {
+            let end = if within { page_bit_index(from) } else { PAGE_BITS - 1 };
+            bits.prev_set(end)
+                .map(|i| base_address(row_index, PageLevel) + i as i128)
+        }
+
+        Node::Branch { level, children, .. } => {
+            let end = if within { child_index(from, ChildOfBranchLevel(*level)) } else { BRANCH_FACTOR - 1 };
+            children[..=end].iter()
+                .rev()
+                .flatten()
+                .find_map(|&child| search_prev_set(arena, child, from))
+        }
+    }
+}
+
+/// Index of an address's bit within its page.
+pub fn page_bit_index(address: i128) -> usize {
+    child_index(address, WordLevel) * 8 + child_index(address, BitLevel)
+}
@@ -0,0 +1,117 @@
+use super::background::Background;
+use super::store::{BitStore, DirectCursor};
+
+use std::collections::BTreeSet;
+use std::mem::size_of;
+
+/// Memory stored as the set of addresses of bits differing from the
+/// background.
+///
+/// Simple enough to be obviously correct, so suited as a reference to check
+/// other stores against, and reasonably efficient for very sparse patterns.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct SetMemory {
+    ones: BTreeSet<i128>,
+    background: Background,
+}
+
+impl SetMemory {
+    pub fn new() -> Self {
+        SetMemory::default()
+    }
+
+    /// Memory holding a background at every address.
+    pub fn with_background(background: Background) -> Self {
+        SetMemory {
+            ones: BTreeSet::new(),
+            background,
+        }
+    }
+
+    pub fn background(&self) -> &Background {
+        &self.background
+    }
+
+    pub fn get_bit(&self, address: i128) -> bool {
+        self.ones.contains(&address) ^ self.background.get(address)
+    }
+
+    pub fn set_bit(&mut self, address: i128, bit: bool) {
+        if bit ^ self.background.get(address) {
+            self.ones.insert(address);
+        } else {
+            self.ones.remove(&address);
+        }
+    }
+
+    /// Iterate over the addresses of bits differing from the background, in
+    /// ascending order.
+    ///
+    /// Reverse the iterator for descending order.
+    pub fn ones(&self) -> impl DoubleEndedIterator<Item=i128> + '_ {
+        self.ones.iter().cloned()
+    }
+
+    /// Approximate number of heap bytes allocated for the set, counting only
+    /// the addresses themselves.
+    pub fn heap_bytes(&self) -> usize {
+        self.ones.len() * size_of::<i128>()
+    }
+}
+
+impl BitStore for SetMemory {
+    type Cursor<'a> = DirectCursor<'a, SetMemory>;
+
+    fn with_background(background: Background) -> SetMemory {
+        SetMemory::with_background(background)
+    }
+
+    fn background(&self) -> &Background {
+        SetMemory::background(self)
+    }
+
+    fn get_bit(&self, address: i128) -> bool {
+        SetMemory::get_bit(self, address)
+    }
+
+    fn set_bit(&mut self, address: i128, bit: bool) {
+        SetMemory::set_bit(self, address, bit);
+    }
+
+    fn cursor(&self, position: i128) -> DirectCursor<'_, SetMemory> {
+        DirectCursor::new(self, position)
+    }
+
+    fn ones(&self) -> Box<dyn DoubleEndedIterator<Item=i128> + '_> {
+        Box::new(SetMemory::ones(self))
+    }
+
+    fn heap_bytes(&self) -> usize {
+        SetMemory::heap_bytes(self)
+    }
+
+    fn fill_range(&mut self, start: i128, end: i128, bit: bool) {
+        if start >= end {
+            return;
+        }
+
+        if !self.background.is_zeros() {
+            for address in start..end {
+                self.set_bit(address, bit);
+            }
+        } else if bit {
+            for address in start..end {
+                self.ones.insert(address);
+            }
+        } else {
+            let cleared: Vec<i128> = self.ones.range(start..end).cloned().collect();
+            for address in cleared {
+                self.ones.remove(&address);
+            }
+        }
+    }
+
+    fn bounds(&self) -> Option<(i128, i128)> {
+        Some((*self.ones.first()?, *self.ones.last()?))
+    }
+}
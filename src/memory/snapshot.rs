@@ -0,0 +1,306 @@
+use super::background::Background;
+use super::node::*;
+use super::page::PageBits;
+use super::{Memory, write};
+
+use std::io::{self, Read, Write};
+
+/// Leading bytes of every snapshot.
+pub const MAGIC: [u8; 4] = *b"BPMS";
+
+/// Version of the format written.
+pub const VERSION: u32 = 2;
+
+/// Oldest version of the format read, which lacks the background.
+pub const MIN_VERSION: u32 = 1;
+
+// format, with integers little-endian:
+//
+// - magic
+// - version, as u32
+// - since version 2, the background:
+//   - period, as u64
+//   - pattern, as bytes holding 8 bits each, least significant bit first
+// - number of pages, as u64
+// - for each page with any set bits, in strictly ascending order:
+//   - row index, as u128
+//   - bits, as PAGE_SIZE bytes, holding how each bit differs from the
+//     background
+
+/// Error reading a snapshot.
+#[derive(Debug, Clone)]
+pub struct Error {
+    pub message: String,
+    pub kind: ErrorKind,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum ErrorKind {
+    Io,
+    BadMagic,
+    UnsupportedVersion,
+    InvalidBackground,
+    InvalidPage,
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error {
+            message: e.to_string(),
+            kind: ErrorKind::Io,
+        }
+    }
+}
+
+/// Write a snapshot of memory, one page at a time.
+pub fn write_snapshot<W: Write>(memory: &Memory, mut writer: W) -> io::Result<()> {
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+
+    let pattern = memory.background().pattern();
+    writer.write_all(&(pattern.len() as u64).to_le_bytes())?;
+    let bytes: Vec<u8> = pattern.chunks(8)
+        .map(|byte| byte.iter()
+            .enumerate()
+            .fold(0x00_u8, |value, (i, &bit)| value | ((bit as u8) << i)))
+        .collect();
+    writer.write_all(&bytes)?;
+
+    writer.write_all(&(memory.pages().count() as u64).to_le_bytes())?;
+
+    for (base, bits) in memory.pages() {
+        writer.write_all(&(row_index(base, PageLevel) as u128).to_le_bytes())?;
+        writer.write_all(&bits.to_dense())?;
+    }
+    Ok(())
+}
+
+/// Read a snapshot of memory, one page at a time.
+///
+/// Reads exactly the bytes of the snapshot, so further data may follow it.
+pub fn read_snapshot<R: Read>(mut reader: R) -> Result<Memory, Error> {
+    let mut magic = [0x00; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(Error {
+            message: "not a memory snapshot".to_owned(),
+            kind: ErrorKind::BadMagic,
+        });
+    }
+
+    let mut version = [0x00; 4];
+    reader.read_exact(&mut version)?;
+    let version = u32::from_le_bytes(version);
+    if !(MIN_VERSION..=VERSION).contains(&version) {
+        return Err(Error {
+            message: format!("unsupported snapshot version {}", version),
+            kind: ErrorKind::UnsupportedVersion,
+        });
+    }
+
+    let background = if version >= 2 {
+        let mut period = [0x00; 8];
+        reader.read_exact(&mut period)?;
+        let period = u64::from_le_bytes(period);
+        if period == 0 {
+            return Err(Error {
+                message: "background period is zero".to_owned(),
+                kind: ErrorKind::InvalidBackground,
+            });
+        }
+
+        // read as the bytes arrive, rather than trusting the period
+        let len = period.div_ceil(8);
+        let mut bytes = Vec::new();
+        (&mut reader).take(len).read_to_end(&mut bytes)?;
+        if (bytes.len() as u64) < len {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+
+        let pattern: Vec<bool> = (0..period as usize)
+            .map(|i| bytes[i / 8] & (0x1 << (i % 8)) != 0)
+            .collect();
+        Background::new(&pattern)
+    } else {
+        Background::zeros()
+    };
+
+    let mut page_count = [0x00; 8];
+    reader.read_exact(&mut page_count)?;
+    let page_count = u64::from_le_bytes(page_count);
+
+    // highest row index of any page
+    let max_row_index: u128 = u128::MAX >> PAGE_LOG2;
+
+    let mut memory = Memory::with_background(background);
+    let mut prev_row_index: Option<u128> = None;
+    for _ in 0..page_count {
+        let mut row = [0x00; 16];
+        reader.read_exact(&mut row)?;
+        let row = u128::from_le_bytes(row);
+
+        let mut bits = [0x00; PAGE_SIZE];
+        reader.read_exact(&mut bits)?;
+
+        if row > max_row_index {
+            return Err(Error {
+                message: format!("page row index {} beyond the address space", row),
+                kind: ErrorKind::InvalidPage,
+            });
+        }
+        if prev_row_index.map(|prev| row <= prev).unwrap_or(false) {
+            return Err(Error {
+                message: format!("page row index {} out of order", row),
+                kind: ErrorKind::InvalidPage,
+            });
+        }
+        if bits.iter().all(|&word| word == 0x00) {
+            return Err(Error {
+                message: format!("page row index {} is empty", row),
+                kind: ErrorKind::InvalidPage,
+            });
+        }
+        prev_row_index = Some(row);
+
+        let base = base_address(row as i128, PageLevel);
+        write::update_page(&mut memory.arena, &mut memory.root, base, |page| {
+            *page = PageBits::from_dense(&bits);
+        });
+    }
+    Ok(memory)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(memory: &Memory) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_snapshot(memory, &mut bytes).unwrap();
+        bytes
+    }
+
+    fn error_kind(bytes: &[u8]) -> ErrorKind {
+        read_snapshot(bytes).err().unwrap().kind
+    }
+
+    /// Memory with bits at both ends of the address space, a full page and a
+    /// page dense with set bits.
+    fn sample(background: Background) -> Memory {
+        let mut memory = Memory::with_background(background);
+        memory.set_bit(i128::MIN, true);
+        memory.set_bit(i128::MAX, true);
+        memory.set_bit(-1, true);
+        memory.fill_range(0, 8192, true);
+        for address in (8192..2 * 8192).step_by(3) {
+            memory.set_bit(address, true);
+        }
+        memory
+    }
+
+    /// A version 1 snapshot, with no background, of bits 0 and 2.
+    fn golden_v1() -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"BPMS");
+        bytes.extend_from_slice(&1_u32.to_le_bytes());
+        bytes.extend_from_slice(&1_u64.to_le_bytes());
+        bytes.extend_from_slice(&(0x1_u128 << 114).to_le_bytes());
+        let mut bits = [0x00; PAGE_SIZE];
+        bits[0] = 0x05;
+        bytes.extend_from_slice(&bits);
+        bytes
+    }
+
+    /// A version 2 snapshot of the given pages, with the zeros background.
+    fn with_pages(pages: &[(u128, [u8; PAGE_SIZE])]) -> Vec<u8> {
+        let mut bytes = snapshot(&Memory::new());
+        bytes.truncate(bytes.len() - 8);
+        bytes.extend_from_slice(&(pages.len() as u64).to_le_bytes());
+        for (row, bits) in pages {
+            bytes.extend_from_slice(&row.to_le_bytes());
+            bytes.extend_from_slice(bits);
+        }
+        bytes
+    }
+
+    #[test]
+    fn round_trips() {
+        let backgrounds = [Background::zeros(), Background::new(&[true, false, false])];
+        for background in backgrounds.iter() {
+            let memory = sample(background.clone());
+            let read = read_snapshot(&snapshot(&memory)[..]).unwrap();
+            assert!(read == memory);
+            assert_eq!(read.background().pattern(), background.pattern());
+            assert!(read.get_bit(i128::MIN) && read.get_bit(i128::MAX));
+            assert_eq!(read.page_count(), memory.page_count());
+
+            let empty = Memory::with_background(background.clone());
+            assert!(read_snapshot(&snapshot(&empty)[..]).unwrap() == empty);
+        }
+    }
+
+    #[test]
+    fn reads_trailing_data_untouched() {
+        let memory = sample(Background::zeros());
+        let mut bytes = snapshot(&memory);
+        bytes.extend_from_slice(b"rest");
+
+        let mut reader = &bytes[..];
+        assert!(read_snapshot(&mut reader).unwrap() == memory);
+        assert_eq!(reader, b"rest");
+    }
+
+    #[test]
+    fn reads_version_1() {
+        let memory = read_snapshot(&golden_v1()[..]).unwrap();
+        assert!(memory.background().is_zeros());
+        assert_eq!(memory.ones().collect::<Vec<_>>(), vec![0, 2]);
+    }
+
+    #[test]
+    fn rejects_bad_header() {
+        let mut bytes = golden_v1();
+        bytes[0] = b'X';
+        assert_eq!(error_kind(&bytes), ErrorKind::BadMagic);
+
+        for &version in &[0_u32, VERSION + 1] {
+            let mut bytes = golden_v1();
+            bytes[4..8].copy_from_slice(&version.to_le_bytes());
+            assert_eq!(error_kind(&bytes), ErrorKind::UnsupportedVersion);
+        }
+
+        let mut bytes = snapshot(&Memory::new());
+        bytes[8..16].copy_from_slice(&0_u64.to_le_bytes());
+        assert_eq!(error_kind(&bytes), ErrorKind::InvalidBackground);
+    }
+
+    #[test]
+    fn rejects_invalid_pages() {
+        let mut bits = [0x00; PAGE_SIZE];
+        bits[7] = 0x80;
+        let row = 0x1_u128 << 114;
+
+        assert!(read_snapshot(&with_pages(&[(row, bits), (row + 1, bits)])[..]).is_ok());
+        let invalid = [
+            vec![(row + 1, bits), (row, bits)],
+            vec![(row, bits), (row, bits)],
+            vec![(row, [0x00; PAGE_SIZE])],
+            vec![(u128::MAX >> PAGE_LOG2 << 1, bits)],
+        ];
+        for pages in invalid.iter() {
+            assert_eq!(error_kind(&with_pages(pages)), ErrorKind::InvalidPage);
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_input() {
+        let memory = sample(Background::new(&[true, false, false]));
+        let bytes = snapshot(&memory);
+        for len in 0..bytes.len() {
+            assert_eq!(error_kind(&bytes[..len]), ErrorKind::Io, "{} bytes", len);
+        }
+        for len in 0..golden_v1().len() {
+            assert_eq!(error_kind(&golden_v1()[..len]), ErrorKind::Io, "{} bytes", len);
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use super::background::Background;
+use super::node::{PAGE_SIZE, range_len};
+use super::{Memory, cursor};
+
+use std::hash::Hash;
+
+/// Storage of a bit at every address, which the runtime can be run over.
+///
+/// Every store holds a background at each address not written, which
+/// defaults to all no. Stores are equal if they hold the same background
+/// and bits, however they are laid out.
+///
+/// Only `with_background`, `background`, `get_bit`, `set_bit`, `ones`,
+/// `heap_bytes` and `cursor` are required, the remaining operations being
+/// derived from them, and overridden where a store can do better.
+pub trait BitStore: Clone + Default + Eq + Hash {
+    /// Cursor reading bits relative to a position.
+    type Cursor<'a>: BitCursor where Self: 'a;
+
+    /// Store holding a background at every address.
+    fn with_background(background: Background) -> Self;
+
+    fn background(&self) -> &Background;
+
+    fn get_bit(&self, address: i128) -> bool;
+
+    fn set_bit(&mut self, address: i128, bit: bool);
+
+    /// Read-only cursor starting at some address.
+    fn cursor(&self, position: i128) -> Self::Cursor<'_>;
+
+    /// Iterate over the addresses of bits differing from the background, which
+    /// without a background are the set bits, in ascending order.
+    ///
+    /// Reverse the iterator for descending order.
+    fn ones(&self) -> Box<dyn DoubleEndedIterator<Item=i128> + '_>;
+
+    /// Number of heap bytes allocated for the bits.
+    fn heap_bytes(&self) -> usize;
+
+    /// Number of pages allocated, or for stores without pages, the number of
+    /// pages their heap allocations would fill.
+    fn page_count(&self) -> usize {
+        self.heap_bytes().div_ceil(PAGE_SIZE)
+    }
+
+    /// Read the bits of the address range `[start, end)`, in ascending order.
+    fn read_range(&self, start: i128, end: i128) -> Vec<bool> {
+        (0..range_len(start, end))
+            .map(|i| self.get_bit(start.wrapping_add(i as i128)))
+            .collect()
+    }
+
+    /// Write a sequence of bits, starting at some address.
+    ///
+    /// Bits which would be beyond the end of the address space are ignored.
+    fn write_range(&mut self, start: i128, bits: &[bool]) {
+        for (i, &bit) in bits.iter().enumerate() {
+            match start.checked_add(i as i128) {
+                Some(address) => self.set_bit(address, bit),
+                None => break,
+            }
+        }
+    }
+
+    /// Set every bit of the address range `[start, end)` to the same value.
+    fn fill_range(&mut self, start: i128, end: i128, bit: bool) {
+        for i in 0..range_len(start, end) {
+            self.set_bit(start.wrapping_add(i as i128), bit);
+        }
+    }
+
+    /// The lowest and highest addresses of bits differing from the
+    /// background, if any.
+    fn bounds(&self) -> Option<(i128, i128)> {
+        let mut ones = self.ones();
+        let low = ones.next()?;
+        let high = ones.next_back().unwrap_or(low);
+        Some((low, high))
+    }
+
+    /// Copy with all content translated by an offset.
+    ///
+    /// Bits which would move beyond the address space are discarded.
+    fn shifted(&self, offset: i128) -> Self {
+        let background = self.background().shifted(offset);
+        let mut shifted = Self::with_background(background.clone());
+        for address in self.ones() {
+            if let Some(address) = address.checked_add(offset) {
+                shifted.set_bit(address, !background.get(address));
+            }
+        }
+        shifted
+    }
+}
+
+/// Reader of bits relative to a position which moves around.
+pub trait BitCursor {
+    /// Move to an absolute address.
+    fn move_to(&mut self, position: i128);
+
+    /// Read the bit at an offset from the current position.
+    ///
+    /// Reads beyond the address space yield no.
+    fn get(&mut self, offset: i128) -> bool;
+}
+
+/// Cursor over a store which reads every bit from the store directly, for
+/// stores with nothing worth caching between reads.
+pub struct DirectCursor<'a, S> {
+    store: &'a S,
+    position: i128,
+}
+
+impl<'a, S: BitStore> DirectCursor<'a, S> {
+    pub fn new(store: &'a S, position: i128) -> Self {
+        DirectCursor {
+            store,
+            position,
+        }
+    }
+}
+
+impl<'a, S: BitStore> BitCursor for DirectCursor<'a, S> {
+    fn move_to(&mut self, position: i128) {
+        self.position = position;
+    }
+
+    fn get(&mut self, offset: i128) -> bool {
+        self.position.checked_add(offset)
+            .map(|address| self.store.get_bit(address))
+            .unwrap_or(false)
+    }
+}
+
+impl BitStore for Memory {
+    type Cursor<'a> = cursor::Cursor<'a>;
+
+    fn with_background(background: Background) -> Memory {
+        Memory::with_background(background)
+    }
+
+    fn background(&self) -> &Background {
+        Memory::background(self)
+    }
+
+    fn get_bit(&self, address: i128) -> bool {
+        Memory::get_bit(self, address)
+    }
+
+    fn set_bit(&mut self, address: i128, bit: bool) {
+        Memory::set_bit(self, address, bit);
+    }
+
+    fn cursor(&self, position: i128) -> cursor::Cursor<'_> {
+        Memory::cursor(self, position)
+    }
+
+    fn ones(&self) -> Box<dyn DoubleEndedIterator<Item=i128> + '_> {
+        Box::new(Memory::ones(self))
+    }
+
+    fn heap_bytes(&self) -> usize {
+        Memory::heap_bytes(self)
+    }
+
+    fn page_count(&self) -> usize {
+        Memory::page_count(self)
+    }
+
+    fn read_range(&self, start: i128, end: i128) -> Vec<bool> {
+        Memory::read_range(self, start, end)
+    }
+
+    fn write_range(&mut self, start: i128, bits: &[bool]) {
+        Memory::write_range(self, start, bits);
+    }
+
+    fn fill_range(&mut self, start: i128, end: i128, bit: bool) {
+        Memory::fill_range(self, start, end, bit);
+    }
+
+    fn bounds(&self) -> Option<(i128, i128)> {
+        Memory::bounds(self)
+    }
+
+    fn shifted(&self, offset: i128) -> Memory {
+        Memory::shifted(self, offset)
+    }
+}
+
+impl<'a> BitCursor for cursor::Cursor<'a> {
+    fn move_to(&mut self, position: i128) {
+        cursor::Cursor::move_to(self, position);
+    }
+
+    fn get(&mut self, offset: i128) -> bool {
+        cursor::Cursor::get(self, offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::dense::DenseMemory;
+    use super::super::set::SetMemory;
+
+    /// Apply the same pseudo-random writes to a store and to `Memory`, then
+    /// check that every operation agrees between them.
+    fn check_matches_memory<S: BitStore>(background: Background) {
+        let mut store = S::with_background(background.clone());
+        let mut memory = Memory::with_background(background);
+
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = move |n: u64| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state % n
+        };
+        for _ in 0..2000 {
+            let address = next(20_000) as i128 - 10_000;
+            match next(4) {
+                0 | 1 => {
+                    let bit = next(2) == 0;
+                    store.set_bit(address, bit);
+                    memory.set_bit(address, bit);
+                }
+                2 => {
+                    let bits: Vec<bool> = (0..next(100)).map(|_| next(3) == 0).collect();
+                    store.write_range(address, &bits);
+                    memory.write_range(address, &bits);
+                }
+                _ => {
+                    let (end, bit) = (address + next(300) as i128, next(2) == 0);
+                    store.fill_range(address, end, bit);
+                    memory.fill_range(address, end, bit);
+                }
+            }
+        }
+
+        assert!(store.ones().eq(memory.ones()));
+        assert!(store.ones().rev().eq(memory.ones().rev()));
+        assert_eq!(store.bounds(), memory.bounds());
+        assert_eq!(store.read_range(-10_100, 10_100), memory.read_range(-10_100, 10_100));
+
+        let (mut cursor, mut memory_cursor) = (store.cursor(0), memory.cursor(0));
+        for address in (-10_100..10_100).step_by(7) {
+            cursor.move_to(address);
+            memory_cursor.move_to(address);
+            for offset in -3..=3 {
+                assert_eq!(BitCursor::get(&mut cursor, offset), memory_cursor.get(offset));
+            }
+        }
+
+        let (shifted, memory_shifted) = (store.shifted(-12_345), memory.shifted(-12_345));
+        assert!(shifted.ones().eq(memory_shifted.ones()));
+        assert_eq!(shifted.background(), memory_shifted.background());
+    }
+
+    #[test]
+    fn dense_memory_matches_memory() {
+        check_matches_memory::<DenseMemory>(Background::zeros());
+        check_matches_memory::<DenseMemory>(Background::new(&[true, false, false]));
+    }
+
+    #[test]
+    fn set_memory_matches_memory() {
+        check_matches_memory::<SetMemory>(Background::zeros());
+        check_matches_memory::<SetMemory>(Background::new(&[true, false, false]));
+    }
+}
@@ -0,0 +1,329 @@
+use super::node::*;
+use super::twiddling::*;
+use super::page::{PageBits, PAGE_BITS};
+use super::{Memory, write};
+
+use std::cmp::Ordering;
+use std::ops::{BitAnd, BitOr, BitXor};
+
+/// Highest row index of any page.
+const MAX_PAGE_ROW_INDEX: i128 = (u128::MAX >> PAGE_LOG2) as i128;
+
+impl Memory {
+    /// Copy of memory with all content translated by an offset.
+    ///
+    /// Bits which would move beyond the address space are discarded.
+    pub fn shifted(&self, offset: i128) -> Memory {
+        let mut shifted = self.shifted_by_pages(floor_div(offset, PAGE_BITS as i128), floor_rem(offset, PAGE_BITS as i128) as usize);
+        shifted.background = self.background.shifted(offset);
+        shifted
+    }
+
+    /// Copy of memory mirrored about an address, such that each bit at `a`
+    /// moves to `2 * center - a`.
+    ///
+    /// Bits which would move beyond the address space are discarded.
+    pub fn reflected(&self, center: i128) -> Memory {
+        // reversing every page maps each address `a` to `!a`, which is
+        // `-1 - a`, so mirroring is that followed by shifting `2 * center + 1`
+        let mut reversed = Memory::new();
+        for (base, bits) in self.pages() {
+            let mut reversed_bits = [0x00; PAGE_SIZE];
+            for (word, &source) in reversed_bits.iter_mut().rev().zip(bits.to_dense().iter()) {
+                *word = source.reverse_bits();
+            }
+            let row_index = MAX_PAGE_ROW_INDEX - row_index(base, PageLevel);
+            reversed.or_page(row_index, &reversed_bits);
+        }
+
+        let center_pages = floor_div(center, PAGE_BITS as i128);
+        let center_bits = floor_rem(center, PAGE_BITS as i128);
+        let offset_bits = 2 * center_bits + 1;
+        let mut reflected = reversed.shifted_by_pages(
+            2 * center_pages + offset_bits / PAGE_BITS as i128,
+            (offset_bits % PAGE_BITS as i128) as usize,
+        );
+        reflected.background = self.background.reflected(center);
+        reflected
+    }
+
+    /// Flip every bit of the address range `[start, end)`.
+    pub fn complement_range(&mut self, start: i128, end: i128) {
+        for (segment_start, bit_index, len) in page_segments(start, range_len(start, end)) {
+            write::update_page(&mut self.arena, &mut self.root, segment_start, |page| {
+                page.update(|words| {
+                    let end_bit = bit_index + len;
+                    let mut page_bit = bit_index;
+                    while page_bit < end_bit {
+                        if page_bit.is_multiple_of(8) && page_bit + 8 <= end_bit {
+                            // whole words at once
+                            words[page_bit / 8] = !words[page_bit / 8];
+                            page_bit += 8;
+                        } else {
+                            words[page_bit / 8] ^= 0x1 << (page_bit % 8);
+                            page_bit += 1;
+                        }
+                    }
+                })
+            });
+
+            write::prune_page(&mut self.arena, &mut self.root, segment_start);
+        }
+    }
+
+    /// Combine two memories bit by bit into a third, one page at a time.
+    ///
+    /// Their backgrounds are combined likewise.
+    pub fn combine<F>(&self, other: &Memory, op: F) -> Memory
+        where F: Fn(u8, u8) -> u8
+    {
+        const EMPTY: PageBits = PageBits::Zeros;
+
+        let mut combined = Memory::with_background(self.background.combine(&other.background, &op));
+        let mut a = self.pages().peekable();
+        let mut b = other.pages().peekable();
+        loop {
+            // take the lower page of each side, or both if they coincide
+            let (base, bits_a, bits_b) = match (a.peek(), b.peek()) {
+                (None, None) => break,
+                (Some(&(base_a, _)), Some(&(base_b, _))) => match base_a.cmp(&base_b) {
+                    Ordering::Less => (base_a, a.next().unwrap().1, &EMPTY),
+                    Ordering::Greater => (base_b, &EMPTY, b.next().unwrap().1),
+                    Ordering::Equal => (base_a, a.next().unwrap().1, b.next().unwrap().1),
+                },
+                (Some(&(base_a, _)), None) => (base_a, a.next().unwrap().1, &EMPTY),
+                (None, Some(&(base_b, _))) => (base_b, &EMPTY, b.next().unwrap().1),
+            };
+
+            // combine the bits themselves, rather than how they differ from
+            // each background
+            let mut bits = combined.background.page_words(base);
+            let (bits_a, bits_b) = (bits_a.to_dense(), bits_b.to_dense());
+            let background_a = self.background.page_words(base);
+            let background_b = other.background.page_words(base);
+            for (i, word) in bits.iter_mut().enumerate() {
+                *word ^= op(bits_a[i] ^ background_a[i], bits_b[i] ^ background_b[i]);
+            }
+            combined.or_page(row_index(base, PageLevel), &bits);
+        }
+        combined
+    }
+
+    /// Shift content by a number of pages plus a number of bits less than a
+    /// page.
+    fn shifted_by_pages(&self, offset_pages: i128, offset_bits: usize) -> Memory {
+        let mut shifted = Memory::new();
+        for (base, bits) in self.pages() {
+            // each page lands across at most two pages
+            let row_index = row_index(base, PageLevel) + offset_pages;
+            let bits = bits.to_dense();
+            shifted.or_page(row_index, &shift_page(&bits, offset_bits as isize));
+            if offset_bits != 0 {
+                shifted.or_page(row_index + 1, &shift_page(&bits, offset_bits as isize - PAGE_BITS as isize));
+            }
+        }
+        shifted
+    }
+
+    /// Set bits of the page at some row index, unless there are none to set,
+    /// or the row index is beyond the address space.
+    fn or_page(&mut self, row_index: i128, bits: &[u8; PAGE_SIZE]) {
+        if !(0..=MAX_PAGE_ROW_INDEX).contains(&row_index) || bits.iter().all(|&word| word == 0x00) {
+            return;
+        }
+
+        write::update_page(&mut self.arena, &mut self.root, base_address(row_index, PageLevel), |page| {
+            page.update(|words| {
+                for (word, &source) in words.iter_mut().zip(bits.iter()) {
+                    *word |= source;
+                }
+            })
+        });
+    }
+}
+
+/// Bits of a page moved up by some number of bits, which may be negative,
+/// discarding those moved beyond the page.
+fn shift_page(bits: &[u8; PAGE_SIZE], shift: isize) -> [u8; PAGE_SIZE] {
+    let word_shift = shift.div_euclid(8);
+    let bit_shift = shift.rem_euclid(8) as u32;
+
+    let word = |index: isize| -> u8 {
+        if (0..PAGE_SIZE as isize).contains(&index) {
+            bits[index as usize]
+        } else {
+            0x00
+        }
+    };
+
+    let mut shifted = [0x00; PAGE_SIZE];
+    for (index, target) in shifted.iter_mut().enumerate() {
+        let source = index as isize - word_shift;
+        *target = if bit_shift == 0 {
+            word(source)
+        } else {
+            (word(source) << bit_shift) | (word(source - 1) >> (8 - bit_shift))
+        };
+    }
+    shifted
+}
+
+macro_rules! impl_memory_op {
+    ($op:tt::$method:tt) => {
+        impl<'a, 'b> $op<&'b Memory> for &'a Memory {
+            type Output = Memory;
+
+            fn $method(self, rhs: &'b Memory) -> Memory {
+                self.combine(rhs, $op::$method)
+            }
+        }
+    }
+}
+
+impl_memory_op!(BitAnd::bitand);
+impl_memory_op!(BitOr::bitor);
+impl_memory_op!(BitXor::bitxor);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::background::Background;
+
+    /// Addresses either side of page boundaries and at the ends of the
+    /// address space.
+    const ANCHORS: [i128; 12] = [
+        i128::MIN, i128::MIN + 1, -8193, -8192, -1, 0, 5, 8191, 8192, 20_000, i128::MAX - 1, i128::MAX,
+    ];
+
+    fn backgrounds() -> Vec<Background> {
+        vec![
+            Background::zeros(),
+            Background::new(&[true, false, false]),
+            Background::new(&[true, true, false, false, false]),
+        ]
+    }
+
+    /// Memory with bits set at each anchor and a pattern across a page
+    /// boundary, starting at some phase.
+    fn sample(background: Background, phase: i128) -> Memory {
+        let mut memory = Memory::with_background(background);
+        for &address in &ANCHORS {
+            memory.set_bit(address, true);
+        }
+        for address in 8000..8400 {
+            if (address + phase) % 5 < 2 {
+                memory.set_bit(address, true);
+            }
+        }
+        memory
+    }
+
+    /// Addresses near each anchor, and around any other addresses of
+    /// interest.
+    fn probes(extra: &[i128]) -> Vec<i128> {
+        ANCHORS.iter().chain(extra)
+            .flat_map(|&anchor| (-70..=70).filter_map(move |d| anchor.checked_add(d)))
+            .chain(7990..8410)
+            .collect()
+    }
+
+    #[test]
+    fn shifted_matches_get_bit() {
+        let offsets = [0, 1, -1, 8191, 8193, -20_000, i128::MAX / 3, i128::MAX, i128::MIN];
+        for background in backgrounds() {
+            let memory = sample(background, 0);
+            for &offset in &offsets {
+                let shifted = memory.shifted(offset);
+                let images: Vec<i128> = ANCHORS.iter().filter_map(|a| a.checked_add(offset)).collect();
+                for address in probes(&images) {
+                    if let Some(source) = address.checked_sub(offset) {
+                        assert_eq!(shifted.get_bit(address), memory.get_bit(source), "{} by {}", address, offset);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn reflected_matches_get_bit() {
+        let centers = [0, -1, 3, 4096, -10_000, i128::MAX / 2, i128::MIN / 2, i128::MAX, i128::MIN];
+        for background in backgrounds() {
+            let memory = sample(background, 0);
+            for &center in &centers {
+                // `2 * center - a`, where it lies within the address space
+                let mirror = |a: i128| center.checked_sub(a).and_then(|d| d.checked_add(center));
+
+                let reflected = memory.reflected(center);
+                let images: Vec<i128> = ANCHORS.iter().filter_map(|&a| mirror(a)).collect();
+                for address in probes(&images) {
+                    if let Some(source) = mirror(address) {
+                        assert_eq!(reflected.get_bit(address), memory.get_bit(source), "{} about {}", address, center);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn complement_range_matches_get_bit() {
+        let ranges = [
+            (-10, 10),
+            (5, 5),
+            (10, 5),
+            (-8200, 8200),
+            (8000, 8400),
+            (i128::MIN, i128::MIN + 20_000),
+            (i128::MAX - 100, i128::MAX),
+        ];
+        for background in backgrounds() {
+            let memory = sample(background, 0);
+            for &(start, end) in &ranges {
+                let mut complemented = memory.clone();
+                complemented.complement_range(start, end);
+                for address in probes(&[start, end]) {
+                    let inside = (start..end).contains(&address);
+                    assert_eq!(complemented.get_bit(address), memory.get_bit(address) ^ inside, "{} in {}..{}", address, start, end);
+                }
+
+                // complementing twice restores the original
+                complemented.complement_range(start, end);
+                assert!(complemented == memory);
+            }
+        }
+    }
+
+    #[test]
+    fn combine_matches_get_bit() {
+        type Op = fn(u8, u8) -> u8;
+        let ops: [(&str, Op); 4] = [
+            ("and", |a, b| a & b),
+            ("or", |a, b| a | b),
+            ("xor", |a, b| a ^ b),
+            ("xnor", |a, b| !(a ^ b)),
+        ];
+        for background_a in backgrounds() {
+            for background_b in backgrounds() {
+                let a = sample(background_a.clone(), 0);
+                let mut b = sample(background_b, 3);
+                b.clear_range(-20, 20);
+                b.set_bit(40_000, true);
+
+                for &(name, op) in &ops {
+                    let combined = a.combine(&b, op);
+                    for address in probes(&[40_000]) {
+                        let expected = op(a.get_bit(address) as u8, b.get_bit(address) as u8) & 0x1 != 0;
+                        assert_eq!(combined.get_bit(address), expected, "{} at {}", name, address);
+                    }
+                }
+
+                let (and, or, xor) = (&a & &b, &a | &b, &a ^ &b);
+                for address in probes(&[40_000]) {
+                    let (bit_a, bit_b) = (a.get_bit(address), b.get_bit(address));
+                    assert_eq!(and.get_bit(address), bit_a & bit_b);
+                    assert_eq!(or.get_bit(address), bit_a | bit_b);
+                    assert_eq!(xor.get_bit(address), bit_a ^ bit_b);
+                }
+            }
+        }
+    }
+}
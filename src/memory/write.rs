@@ -1,93 +1,266 @@
 
+use super::arena::{Arena, NodeId};
 use super::node::*;
+use super::page::PageBits;
+use super::read::search_page;
 use super::twiddling::*;
 
-use std::mem;
+pub fn insert_bit(
+    arena: &mut Arena,
+    root: &mut NodeId,
+    address: i128,
+    bit: bool,
+) {
+    // writing no to an untouched region changes nothing
+    if !bit && search_page(arena, *root, address).is_none() {
+        return;
+    }
 
-pub fn insert_bit(root: &mut Box<Node>, address: i128, bit: bool) {
-    let mut curr: &mut Box<Node> = root;
-    loop {
-        // ascend
-        ascend(curr, address);
+    let page = insert_page(arena, root, address);
+    let bit_index = child_index(address, WordLevel) * 8 + child_index(address, BitLevel);
+    let empty = arena.update_page(page, |bits| {
+        bits.set(bit_index, bit);
+        bits.is_zeros()
+    });
+
+    if empty {
+        prune_page(arena, root, address);
+    }
+}
+
+/// Modify the bits of the page containing an address, creating it if it does
+/// not exist.
+pub fn update_page<R, F>(
+    arena: &mut Arena,
+    root: &mut NodeId,
+    address: i128,
+    modify: F,
+) -> R
+    where F: FnOnce(&mut PageBits) -> R
+{
+    let page = insert_page(arena, root, address);
+    arena.update_page(page, modify)
+}
 
+/// Find the page containing an address, creating it if it does not exist.
+fn insert_page(
+    arena: &mut Arena,
+    root: &mut NodeId,
+    address: i128,
+) -> NodeId {
+    // an empty root page holds nothing worth keeping, so it is replaced
+    // rather than left behind as an empty child
+    let replace_root = match &arena[*root] {
+        Node::Page { row_index, bits } => {
+            *row_index != super::node::row_index(address, PageLevel) && bits.is_zeros()
+        }
+        Node::Branch { .. } => false,
+    };
+    if replace_root {
+        *root = arena.make_mut(*root);
+        arena[*root] = Node::page_containing_address(address);
+    }
+
+    // ascend
+    *root = ascend(arena, *root, address);
+
+    let mut curr: NodeId = *root;
+    loop {
         // descend
-        let descend_to: usize = match Box::as_mut(curr) {
+        let (level, child): (BranchLevel, Option<NodeId>) = match arena[curr] {
 
-            &mut Node::Page {
-                ref mut bits,
+            Node::Page {
                 row_index: page_row_index,
+                ..
             } => {
                 debug_assert_eq!(page_row_index, row_index(address, PageLevel));
 
-                let word_index = child_index(address, WordLevel);
-                let bit_index = child_index(address, BitLevel) as u8;
-
-                let word: &mut u8 = &mut bits[word_index];
-                set_word_bit(word, bit_index, bit);
-
-                return;
+                break;
             }
 
-            &mut Node::Branch {
+            Node::Branch {
                 level,
-                children: _,
+                ref children,
                 row_index: branch_row_index,
             } => {
                 debug_assert_eq!(branch_row_index, row_index(address, level));
 
-                child_index(address, ChildOfBranchLevel(level))
+                (level, children[child_index(address, ChildOfBranchLevel(level))])
             }
 
         };
 
-        // descend part #2 (because lifetime limitations)
-        if let &mut Node::Branch {
-            ref mut children,
-            ..
-        } = Box::as_mut(curr) {
-
-            curr = children[descend_to]
-                .get_or_insert_with(|| {
-                    Box::new(Node::page_containing_address(address))
-                });
+        // ascend from the child, or create it
+        let next: NodeId = match child {
+            Some(child) => ascend(arena, child, address),
+            None => arena.alloc(Node::page_containing_address(address)),
+        };
 
-        } else {
-            unreachable!()
+        if let Node::Branch { children, .. } = &mut arena[curr] {
+            children[child_index(address, ChildOfBranchLevel(level))] = Some(next);
         }
+        curr = next;
+    }
+
+    curr
+}
+
+/// Insert a new branch above a node, if necessary, such that the node and the
+/// address share a row, returning the node now in its place, which is never
+/// shared, so may be modified.
+fn ascend(arena: &mut Arena, id: NodeId, address: i128) -> NodeId {
+    let node: &Node = &arena[id];
+    if node.row_index() == node.row_index_of_address(address) {
+        return arena.make_mut(id);
+    }
+
+    const FACTOR: i128 = BRANCH_FACTOR as i128;
+
+    // climb to the lowest level at which the node and address share a row
+    let mut level: BranchLevel = node.parent_level();
+    let mut child_row_index: i128 = node.row_index();
+    let mut row_index: i128 = floor_div(child_row_index, FACTOR);
+
+    while row_index != super::node::row_index(address, level) {
+        level = level.parent();
+        child_row_index = row_index;
+        row_index = floor_div(row_index, FACTOR);
+    }
+
+    let reattach_index: i128 = floor_rem(child_row_index, FACTOR);
+
+    let mut children: Box<[Option<NodeId>; BRANCH_FACTOR]> = Box::new([None; BRANCH_FACTOR]);
+    children[reattach_index as usize] = Some(id);
+    arena.alloc(Node::Branch {
+        level,
+        row_index,
+        children,
+    })
+}
+
+/// Remove the page containing an address if it holds no set bits, collapsing
+/// any branches this leaves with only a single child.
+///
+/// An empty page is left in place if it is the root.
+pub fn prune_page(arena: &mut Arena, root: &mut NodeId, address: i128) {
+    if let Node::Page { .. } = arena[*root] {
+        return;
     }
+
+    *root = match prune(arena, *root, address) {
+        Some(id) => id,
+        None => arena.alloc(Node::page(0)),
+    };
 }
 
-fn ascend(node: &mut Box<Node>, address: i128) {
+/// Prune the page containing an address from a subtree, returning the node
+/// now in the subtree's place, if any remains.
+fn prune(arena: &mut Arena, id: NodeId, address: i128) -> Option<NodeId> {
+    let node: &Node = &arena[id];
     if node.row_index() != node.row_index_of_address(address) {
-        const FACTOR: i128 = BRANCH_FACTOR as i128;
+        return Some(id);
+    }
+    let id = arena.make_mut(id);
+    let node: &Node = &arena[id];
 
-        let mut level: BranchLevel = node.parent_level();
-        let mut row_index: i128 = floor_div(node.row_index(), FACTOR);
-        let mut reattach_index: i128 = floor_rem(node.row_index(), FACTOR);
+    let (i, child): (usize, Option<NodeId>) = match node {
+        Node::Page { bits, .. } => {
+            if bits.is_zeros() {
+                arena.free(id);
+                return None;
+            }
+            return Some(id);
+        }
 
-        while reattach_index != child_index(address, level) as i128 {
-            level = level.parent();
-            row_index = floor_div(row_index, FACTOR);
-            reattach_index /= FACTOR;
+        Node::Branch { level, children, .. } => {
+            let i = child_index(address, ChildOfBranchLevel(*level));
+            (i, children[i])
         }
+    };
 
-        let parent: Box<Node> = Box::new(Node::Branch {
-            level,
-            row_index,
-            children: new_children(),
-        });
-        let child: Box<Node> = mem::replace(node, parent);
+    let child = child.and_then(|child| prune(arena, child, address));
 
-        if let &mut Node::Branch {
-            ref mut children,
-            ..
-        } = Box::as_mut(node) {
+    if let Node::Branch { children, .. } = &mut arena[id] {
+        children[i] = child;
 
-            children[reattach_index as usize] = Some(child);
+        let mut remaining = children.iter().flatten();
+        match (remaining.next().copied(), remaining.next()) {
+            (None, _) => {
+                arena.free(id);
+                None
+            }
+            // a branch with a single child is redundant, since children may
+            // skip levels
+            (Some(only_child), None) => {
+                arena.free(id);
+                Some(only_child)
+            }
+            _ => Some(id),
+        }
+    } else {
+        unreachable!()
+    }
+}
 
-        } else {
-            unreachable!()
+#[cfg(test)]
+mod tests {
+    use super::super::Memory;
+
+    #[test]
+    fn writing_no_to_untouched_memory_allocates_nothing() {
+        let mut memory = Memory::new();
+        for &address in &[i128::MIN, -1 << 100, -70_000, 70_000, 1 << 100, i128::MAX] {
+            memory.set_bit(address, false);
+            memory.write_range(address.min(i128::MAX - 100), &[false; 100]);
+            memory.fill_range(address.min(i128::MAX - 100_000), address.min(i128::MAX - 100_000) + 100_000, false);
+        }
+        assert_eq!(memory.page_count(), 1);
+        assert_eq!(memory.tree_layers(), 1);
+    }
+
+    #[test]
+    fn clearing_every_bit_collapses_the_tree() {
+        let addresses = [i128::MIN, -1 << 100, -70_000, 5, 70_000, 1 << 100, i128::MAX];
+        let mut memory = Memory::new();
+        for &address in &addresses {
+            memory.set_bit(address, true);
+        }
+        assert_eq!(memory.page_count(), addresses.len());
+        assert!(memory.tree_layers() > 1);
+
+        for &address in &addresses {
+            memory.set_bit(address, false);
         }
+        assert_eq!(memory.page_count(), 1);
+        assert_eq!(memory.tree_layers(), 1);
 
+        // likewise clearing by range
+        memory.fill_range(-100_000, 100_000, true);
+        memory.fill_range(-100_000, 100_000, false);
+        assert_eq!(memory.page_count(), 1);
+        assert_eq!(memory.tree_layers(), 1);
+    }
+
+    #[test]
+    fn moving_pattern_keeps_pages_bounded() {
+        // a glider-like pattern of three bits, moving across 40 pages
+        let shape = [0, 1, 3];
+        let mut memory = Memory::new();
+        let mut position: i128 = -20 * 8192;
+        for &offset in &shape {
+            memory.set_bit(position + offset, true);
+        }
+        while position < 20 * 8192 {
+            for &offset in &shape {
+                memory.set_bit(position + offset, false);
+            }
+            position += 1000;
+            for &offset in &shape {
+                memory.set_bit(position + offset, true);
+            }
+            assert!(memory.page_count() <= 2);
+            assert!(memory.tree_layers() <= 3);
+        }
+        assert_eq!(memory.ones().collect::<Vec<_>>(), shape.iter().map(|&offset| position + offset).collect::<Vec<_>>());
     }
 }
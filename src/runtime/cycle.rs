@@ -0,0 +1,209 @@
+use std::collections::{HashMap, VecDeque};
+use std::mem::size_of;
+
+/// Most generations a detector remembers, after which it forgets the oldest,
+/// so that only periods up to this many ticks are detected.
+pub const MAX_GENERATIONS: usize = 0x1 << 16;
+
+/// A repeated state, after which the program evolves periodically.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct Cycle {
+    /// Tick at which the repeated state first occurred, among those still
+    /// remembered.
+    pub start: u64,
+    /// Number of ticks between repetitions.
+    pub period: u64,
+}
+
+/// State of a generation, as remembered by a detector.
+pub trait State: Eq {
+    /// Number of heap bytes the state holds.
+    fn heap_bytes(&self) -> usize;
+}
+
+/// Bounded record of generations' states, looked up by hash, each with some
+/// value describing where it occurred.
+///
+/// Generations with equal hashes are compared in full, so a hash collision
+/// never matches different states.
+#[derive(Clone, Debug)]
+pub struct History<T, V> {
+    /// State of each generation, by hash, oldest first.
+    generations: HashMap<u64, VecDeque<(T, V)>>,
+    /// Hash of each generation, oldest first.
+    order: VecDeque<u64>,
+    capacity: usize,
+    /// Heap bytes held by the states.
+    state_bytes: usize,
+}
+
+impl<T: State, V: Copy> History<T, V> {
+    /// History remembering up to `capacity` generations.
+    pub fn with_capacity(capacity: usize) -> Self {
+        History {
+            generations: HashMap::new(),
+            order: VecDeque::new(),
+            capacity,
+            state_bytes: 0,
+        }
+    }
+
+    /// Forget all generations.
+    pub fn clear(&mut self) {
+        self.generations.clear();
+        self.order.clear();
+        self.state_bytes = 0;
+    }
+
+    /// The value recorded with an earlier generation of the same state, or
+    /// otherwise remember the state with a value, forgetting the oldest
+    /// generation if at capacity.
+    pub fn find_or_insert(&mut self, hash: u64, state: T, value: V) -> Option<V> {
+        let generations = self.generations.entry(hash).or_default();
+        if let Some(&(_, earlier)) = generations.iter().find(|(earlier, _)| *earlier == state) {
+            return Some(earlier);
+        }
+
+        self.state_bytes += state.heap_bytes();
+        generations.push_back((state, value));
+        self.order.push_back(hash);
+        if self.order.len() > self.capacity {
+            self.forget_oldest();
+        }
+        None
+    }
+
+    fn forget_oldest(&mut self) {
+        let hash = match self.order.pop_front() {
+            Some(hash) => hash,
+            None => return,
+        };
+        let generations = self.generations.get_mut(&hash).unwrap();
+        // generations of equal hash are in order of age too
+        let (state, _) = generations.pop_front().unwrap();
+        self.state_bytes -= state.heap_bytes();
+        if generations.is_empty() {
+            self.generations.remove(&hash);
+        }
+    }
+
+    /// Number of generations remembered.
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// Approximate number of heap bytes allocated, counting the states and
+    /// the bookkeeping of each generation.
+    pub fn heap_bytes(&self) -> usize {
+        self.state_bytes + self.order.capacity() * (size_of::<u64>() + size_of::<(u64, T, V)>())
+    }
+}
+
+/// Detects repeated states by remembering each generation, looked up by its
+/// hash.
+///
+/// A generation's state is its memory content together with which bits are
+/// awake. Once an input is performed the future no longer follows from the
+/// state alone, so any input clears the history. Without input the evolution
+/// is deterministic, so output within a cycle is periodic too.
+///
+/// Generations with equal hashes are compared in full, so a hash collision
+/// never reports a false cycle. Only the latest `MAX_GENERATIONS` are
+/// remembered, unless given another capacity.
+#[derive(Clone, Debug)]
+pub struct CycleDetector<T> {
+    /// State of each generation, with the tick it first occurred.
+    history: History<T, u64>,
+}
+
+impl<T: State> CycleDetector<T> {
+    pub fn new() -> Self {
+        CycleDetector::with_capacity(MAX_GENERATIONS)
+    }
+
+    /// Detector remembering up to `capacity` generations.
+    pub fn with_capacity(capacity: usize) -> Self {
+        CycleDetector {
+            history: History::with_capacity(capacity),
+        }
+    }
+
+    /// Forget all previous generations.
+    pub fn clear(&mut self) {
+        self.history.clear();
+    }
+
+    /// Record a generation's state and its hash, returning the cycle if it
+    /// repeats an earlier generation.
+    pub fn record(&mut self, tick: u64, hash: u64, state: T) -> Option<Cycle> {
+        self.history.find_or_insert(hash, state, tick)
+            .map(|start| Cycle {
+                start,
+                period: tick - start,
+            })
+    }
+
+    /// Number of generations remembered.
+    pub fn len(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.history.is_empty()
+    }
+
+    /// Approximate number of heap bytes allocated for the history.
+    pub fn heap_bytes(&self) -> usize {
+        self.history.heap_bytes()
+    }
+}
+
+impl<T: State> Default for CycleDetector<T> {
+    fn default() -> Self {
+        CycleDetector::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl State for &str {
+        fn heap_bytes(&self) -> usize {
+            self.len()
+        }
+    }
+
+    #[test]
+    fn hash_collision_is_not_a_cycle() {
+        let mut detector = CycleDetector::new();
+        assert_eq!(detector.record(0, 7, "a"), None);
+        assert_eq!(detector.record(1, 7, "b"), None);
+        assert_eq!(detector.len(), 2);
+
+        assert_eq!(detector.record(3, 7, "b"), Some(Cycle { start: 1, period: 2 }));
+    }
+
+    #[test]
+    fn forgets_oldest_beyond_capacity() {
+        let mut detector = CycleDetector::with_capacity(2);
+        assert_eq!(detector.record(0, 1, "a"), None);
+        assert_eq!(detector.record(1, 7, "bb"), None);
+        assert_eq!(detector.record(2, 7, "ccc"), None);
+        assert_eq!(detector.len(), 2);
+        assert_eq!(detector.history.state_bytes, 5);
+
+        // "a" is forgotten, so its repeat starts a new record
+        assert_eq!(detector.record(3, 1, "a"), None);
+        assert_eq!(detector.record(4, 7, "ccc"), Some(Cycle { start: 2, period: 2 }));
+        assert_eq!(detector.history.state_bytes, 4);
+
+        detector.clear();
+        assert!(detector.is_empty());
+        assert_eq!(detector.history.state_bytes, 0);
+    }
+}
@@ -0,0 +1,30 @@
+use super::Outcome;
+
+use std::fmt;
+
+/// Runtime error, stopping execution cleanly.
+///
+/// Records what was executed by the call which failed.
+#[derive(Debug, Clone)]
+pub struct Error {
+    pub message: String,
+    pub kind: ErrorKind,
+    pub outcome: Outcome,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum ErrorKind {
+    TickLimit,
+    PageLimit,
+    MemoryLimit,
+    AwakeLimit,
+    TimeLimit,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Error {}
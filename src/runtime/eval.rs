@@ -0,0 +1,94 @@
+use crate::code::bytecode::*;
+use crate::code::truthtable::IoTruthTable;
+use crate::memory::background::Background;
+
+/// Truth table of a memory read which yielded yes.
+const READ_YES: IoTruthTable<u8> = IoTruthTable(0x0F);
+
+/// Truth table of a memory read which yielded no.
+const READ_NO: IoTruthTable<u8> = IoTruthTable(0x00);
+
+/// Evaluate the behavior rule for a single bit, producing the truth table of
+/// what the bit would become for each possible result of its I/O.
+///
+/// The `read` function yields the previous state of memory at an offset
+/// relative to the bit being evaluated. The stack is cleared before use, and
+/// only passed in so that its allocation can be reused between bits.
+pub fn evaluate<F>(
+    instrs: &[Instr],
+    stack: &mut Vec<IoTruthTable<u8>>,
+    mut read: F,
+) -> IoTruthTable<u8>
+    where F: FnMut(i128) -> bool
+{
+    stack.clear();
+
+    for instr in instrs {
+        match *instr {
+            Instr::Value(PushInstr::Push(table)) => {
+                stack.push(table);
+            }
+
+            Instr::Value(PushInstr::ReadThenPush { offset }) => {
+                stack.push(if read(offset) { READ_YES } else { READ_NO });
+            }
+
+            Instr::Operation(op) => {
+                let a = stack.pop().expect("bytecode stack underflow");
+                let result = if op.arity() == 1 {
+                    match op {
+                        OpInstr::Not => !a,
+                        _ => unreachable!(),
+                    }
+                } else {
+                    let b = stack.pop().expect("bytecode stack underflow");
+                    match op {
+                        OpInstr::Both      => a & b,
+                        OpInstr::Either    => a | b,
+                        OpInstr::Different => a ^ b,
+                        OpInstr::Same      => !(a ^ b),
+                        OpInstr::Neither   => !(a | b),
+                        OpInstr::Not       => unreachable!(),
+                    }
+                };
+                stack.push(result);
+            }
+        }
+    }
+
+    debug_assert_eq!(stack.len(), 1);
+    stack.pop().expect("bytecode produced no value")
+}
+
+/// Whether every bit of a background, surrounded by the background, becomes
+/// itself and performs no I/O, so that memory nobody writes never changes.
+///
+/// With no background, this is the readme's definition of a stable program:
+/// if all of a bit's reads yield no, it evaluates to no and performs no I/O.
+pub fn is_stable(instrs: &[Instr], background: &Background) -> bool {
+    let period = background.period() as i128;
+    let mut stack = Vec::new();
+    (0..period).all(|address| {
+        let table = evaluate(
+            instrs,
+            &mut stack,
+            |offset| background.get(address + offset.rem_euclid(period)),
+        );
+        !does_output(table)
+            && !does_input(table, false)
+            && table.bitwise_lookup(false, false) == background.get(address)
+    })
+}
+
+/// Whether a bit's result depends on its output, meaning it must output.
+///
+/// Outputs are performed before inputs, so this cannot yet know the input.
+pub fn does_output(table: IoTruthTable<u8>) -> bool {
+    table.bitwise_lookup(false, false) != table.bitwise_lookup(false, true)
+        || table.bitwise_lookup(true, false) != table.bitwise_lookup(true, true)
+}
+
+/// Whether a bit's result depends on its input, given whether it output.
+pub fn does_input(table: IoTruthTable<u8>, output: bool) -> bool {
+    table.bitwise_lookup(false, output) != table.bitwise_lookup(true, output)
+}
@@ -0,0 +1,456 @@
+use std::collections::HashMap;
+use std::mem;
+
+use super::eval;
+use crate::code::bytecode::*;
+use crate::code::bytecode::verify::verify;
+use crate::code::truthtable::IoTruthTable;
+use crate::memory::store::BitStore;
+
+/// Level of a leaf node, which holds a word of 64 bits.
+const LEAF_LEVEL: u32 = 6;
+
+/// Greatest supported radius of a behavior rule.
+pub const MAX_RADIUS: i128 = 0x1 << 20;
+
+/// Greatest level of node, so that addresses within it fit within an `i128`.
+const MAX_LEVEL: u32 = 120;
+
+/// Reason a program cannot be evaluated by `Hashlife`.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum Unsupported {
+    /// The program is not well-formed bytecode.
+    Malformed,
+    /// The result of the program may depend on input or output.
+    Io,
+    /// The program is not stable, so untouched memory may change.
+    Unstable,
+    /// The program reads memory further away than `MAX_RADIUS`.
+    Radius,
+}
+
+/// Index of an interned node.
+type NodeId = u32;
+
+/// Contents of a node.
+///
+/// A node of level `k` spans `2^k` bits. Branches are split into two halves of
+/// the level beneath, and leaves hold their bits in ascending address order,
+/// starting from the least significant bit.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+enum NodeKind {
+    Leaf(u64),
+    Branch(NodeId, NodeId),
+}
+
+/// Memoized evaluation engine for stable, I/O-free programs.
+///
+/// Configurations are stored as hash-consed binary trees, such that identical
+/// regions of memory are represented by the same node. As in hashlife, the
+/// result of advancing the middle of each node is memoized, so large regular
+/// configurations can be advanced exponentially many ticks at once.
+///
+/// For stable, I/O-free programs, evaluating every bit each tick is equivalent
+/// to the runtime's waking up and putting to sleep of bits, so results match
+/// those of the runtime.
+pub struct Hashlife {
+    instrs: Vec<Instr>,
+    /// Greatest distance read from, but at least 1.
+    radius: i128,
+    /// Lowest level of node which is advanced by brute force.
+    base_level: u32,
+    /// Log base 2 of how many ticks a base level node is advanced by.
+    base_step_log2: u32,
+
+    nodes: Vec<(u32, NodeKind)>,
+    interned: HashMap<NodeKind, NodeId>,
+    /// Empty node of each level, starting from the leaf level.
+    empty: Vec<NodeId>,
+    /// Memoized results, keyed by node and log base 2 of ticks advanced.
+    results: HashMap<(NodeId, u32), NodeId>,
+
+    stack: Vec<IoTruthTable<u8>>,
+}
+
+impl Hashlife {
+    pub fn new(program: &CompiledProgram) -> Result<Self, Unsupported> {
+        let stack_info = verify(program.instrs()).map_err(|_| Unsupported::Malformed)?;
+        let metadata = program.metadata();
+        if metadata.uses_input || metadata.uses_output {
+            return Err(Unsupported::Io);
+        }
+        if metadata.radius() > MAX_RADIUS as u128 {
+            return Err(Unsupported::Radius);
+        }
+        if !metadata.stable {
+            return Err(Unsupported::Unstable);
+        }
+        let radius = (metadata.radius() as i128).max(1);
+
+        // the middle half of a base level node must be outside the light cone
+        // of its edges, so the base level must span at least 4 times the radius
+        let radius_log2 = 128 - (radius - 1).leading_zeros();
+        let base_level = (radius_log2 + 2).max(LEAF_LEVEL + 1);
+        let base_step_log2 = (0x1_i128 << (base_level - 2)).checked_div(radius)
+            .map(|steps| 127 - steps.leading_zeros())
+            .unwrap_or(0);
+
+        let mut hashlife = Hashlife {
+            instrs: program.instrs().to_vec(),
+            radius,
+            base_level,
+            base_step_log2,
+            nodes: Vec::new(),
+            interned: HashMap::new(),
+            empty: Vec::new(),
+            results: HashMap::new(),
+            stack: Vec::with_capacity(stack_info.max_depth),
+        };
+        let empty_leaf = hashlife.intern(NodeKind::Leaf(0));
+        hashlife.empty.push(empty_leaf);
+        Ok(hashlife)
+    }
+
+    /// Number of distinct nodes currently interned.
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Forget all interned nodes and memoized results, freeing their memory.
+    pub fn clear_cache(&mut self) {
+        self.nodes.clear();
+        self.interned.clear();
+        self.empty.clear();
+        self.results.clear();
+        let empty_leaf = self.intern(NodeKind::Leaf(0));
+        self.empty.push(empty_leaf);
+    }
+
+    /// Compute the state of memory after some number of ticks.
+    ///
+    /// Returns `None` if the configuration would grow beyond the address
+    /// space.
+    ///
+    /// Panics if memory has a background, as only all no is supported.
+    pub fn advance<S: BitStore>(&mut self, memory: &S, ticks: u64) -> Option<S> {
+        assert!(memory.background().is_zeros(), "hashlife supports no background");
+        let addresses: Vec<i128> = memory.ones().collect();
+        if addresses.is_empty() {
+            return Some(S::default());
+        }
+
+        // build a root node spanning all set bits
+        let origin: i128 = addresses[0];
+        let positions: Vec<u128> = addresses.iter()
+            .map(|&address| address.wrapping_sub(origin) as u128)
+            .collect();
+        let span_log2 = 128 - positions[positions.len() - 1].leading_zeros();
+        let level = span_log2.max(self.base_level);
+        if level > MAX_LEVEL {
+            return None;
+        }
+        let mut root = self.build(&positions, level);
+        let mut origin = origin;
+
+        let mut remaining = ticks;
+        while remaining > 0 {
+            let step_log2 = 63 - remaining.leading_zeros();
+
+            // pad so that the light cone of the content stays within the
+            // middle half of the root
+            let padded = self.pad(root, origin)?;
+            let (mut padded, mut padded_origin) = self.pad(padded.0, padded.1)?;
+            while self.max_step_log2(self.level(padded)) <= step_log2 {
+                let repadded = self.pad(padded, padded_origin)?;
+                padded = repadded.0;
+                padded_origin = repadded.1;
+            }
+
+            let quarter = 0x1_i128 << (self.level(padded) - 2);
+            root = self.advance_node(padded, step_log2);
+            origin = padded_origin + quarter;
+
+            let cropped = self.crop(root, origin);
+            root = cropped.0;
+            origin = cropped.1;
+
+            remaining -= 0x1 << step_log2;
+        }
+
+        let mut memory = S::default();
+        self.write_to(root, origin, &mut memory);
+        Some(memory)
+    }
+
+    fn level(&self, node: NodeId) -> u32 {
+        self.nodes[node as usize].0
+    }
+
+    fn kind(&self, node: NodeId) -> NodeKind {
+        self.nodes[node as usize].1
+    }
+
+    fn children(&self, node: NodeId) -> (NodeId, NodeId) {
+        match self.kind(node) {
+            NodeKind::Branch(left, right) => (left, right),
+            NodeKind::Leaf(_) => unreachable!("leaf has no children"),
+        }
+    }
+
+    fn intern(&mut self, kind: NodeKind) -> NodeId {
+        if let Some(&node) = self.interned.get(&kind) {
+            return node;
+        }
+        let level = match kind {
+            NodeKind::Leaf(_) => LEAF_LEVEL,
+            NodeKind::Branch(left, _) => self.level(left) + 1,
+        };
+        let node = self.nodes.len() as NodeId;
+        self.nodes.push((level, kind));
+        self.interned.insert(kind, node);
+        node
+    }
+
+    fn join(&mut self, left: NodeId, right: NodeId) -> NodeId {
+        debug_assert_eq!(self.level(left), self.level(right));
+        self.intern(NodeKind::Branch(left, right))
+    }
+
+    fn empty(&mut self, level: u32) -> NodeId {
+        while self.empty.len() <= (level - LEAF_LEVEL) as usize {
+            let below = self.empty[self.empty.len() - 1];
+            let node = self.join(below, below);
+            self.empty.push(node);
+        }
+        self.empty[(level - LEAF_LEVEL) as usize]
+    }
+
+    /// Greatest log base 2 of ticks which a node of some level can advance.
+    fn max_step_log2(&self, level: u32) -> u32 {
+        self.base_step_log2 + (level - self.base_level)
+    }
+
+    /// Build a node of some level from sorted positions of set bits within it.
+    fn build(&mut self, positions: &[u128], level: u32) -> NodeId {
+        if positions.is_empty() {
+            return self.empty(level);
+        }
+        if level == LEAF_LEVEL {
+            let word = positions.iter()
+                .fold(0_u64, |word, &position| word | (0x1 << position));
+            return self.intern(NodeKind::Leaf(word));
+        }
+
+        let half: u128 = 0x1 << (level - 1);
+        let split = positions.iter().position(|&position| position >= half)
+            .unwrap_or(positions.len());
+        let right_positions: Vec<u128> = positions[split..].iter()
+            .map(|&position| position - half)
+            .collect();
+
+        let left = self.build(&positions[..split], level - 1);
+        let right = self.build(&right_positions, level - 1);
+        self.join(left, right)
+    }
+
+    /// Wrap a node in a node of the next level, such that it is the middle
+    /// half, returning the new node and its origin.
+    fn pad(&mut self, node: NodeId, origin: i128) -> Option<(NodeId, i128)> {
+        let level = self.level(node);
+        if level >= MAX_LEVEL {
+            return None;
+        }
+        let origin = origin.checked_sub(0x1 << (level - 1))?;
+        origin.checked_add((0x1 << (level + 1)) - 1)?;
+
+        let (left, right) = self.children(node);
+        let empty = self.empty(level - 1);
+        let left = self.join(empty, left);
+        let right = self.join(right, empty);
+        Some((self.join(left, right), origin))
+    }
+
+    /// Middle half of a node, as a node of the level beneath.
+    fn center(&mut self, node: NodeId) -> NodeId {
+        let (left, right) = self.children(node);
+        match (self.kind(left), self.kind(right)) {
+            (NodeKind::Leaf(left), NodeKind::Leaf(right)) => {
+                self.intern(NodeKind::Leaf((left >> 32) | (right << 32)))
+            }
+            _ => {
+                let (_, left_inner) = self.children(left);
+                let (right_inner, _) = self.children(right);
+                self.join(left_inner, right_inner)
+            }
+        }
+    }
+
+    /// Shrink a node while its outer quarters are empty, returning the new
+    /// node and its origin.
+    fn crop(&mut self, mut node: NodeId, mut origin: i128) -> (NodeId, i128) {
+        while self.level(node) > self.base_level {
+            let level = self.level(node);
+            let (left, right) = self.children(node);
+            let empty = self.empty(level - 2);
+            if self.children(left).0 != empty || self.children(right).1 != empty {
+                break;
+            }
+            node = self.center(node);
+            origin += 0x1 << (level - 2);
+        }
+        (node, origin)
+    }
+
+    /// Advance the middle half of a node by `2^step_log2` ticks, as a node of
+    /// the level beneath.
+    fn advance_node(&mut self, node: NodeId, step_log2: u32) -> NodeId {
+        let level = self.level(node);
+        debug_assert!(step_log2 <= self.max_step_log2(level));
+
+        let empty = self.empty(level);
+        if node == empty {
+            return self.empty(level - 1);
+        }
+        if let Some(&result) = self.results.get(&(node, step_log2)) {
+            return result;
+        }
+
+        let result = if level == self.base_level {
+            self.advance_base(node, step_log2)
+        } else {
+            // three overlapping nodes of the level beneath
+            let (left, right) = self.children(node);
+            let (_, left_inner) = self.children(left);
+            let (right_inner, _) = self.children(right);
+            let middle = self.join(left_inner, right_inner);
+
+            // advance their middles by half the ticks, or by none, such that
+            // the remaining ticks are advanced beneath
+            let halved = step_log2 == self.max_step_log2(level);
+            let remaining_log2 = if halved { step_log2 - 1 } else { step_log2 };
+            let thirds = if halved {
+                [
+                    self.advance_node(left, remaining_log2),
+                    self.advance_node(middle, remaining_log2),
+                    self.advance_node(right, remaining_log2),
+                ]
+            } else {
+                [self.center(left), self.center(middle), self.center(right)]
+            };
+
+            let left = self.join(thirds[0], thirds[1]);
+            let right = self.join(thirds[1], thirds[2]);
+            let left = self.advance_node(left, remaining_log2);
+            let right = self.advance_node(right, remaining_log2);
+            self.join(left, right)
+        };
+
+        self.results.insert((node, step_log2), result);
+        result
+    }
+
+    /// Advance the middle half of a base level node by evaluating every bit.
+    fn advance_base(&mut self, node: NodeId, step_log2: u32) -> NodeId {
+        let mut bits: Vec<bool> = Vec::new();
+        self.read_bits(node, &mut bits);
+        let mut next: Vec<bool> = vec![false; bits.len()];
+
+        // the region which can be computed shrinks by the radius each tick
+        let radius = self.radius as usize;
+        for tick in 1..=(0x1_usize << step_log2) {
+            for i in (tick * radius)..(bits.len() - tick * radius) {
+                let table = eval::evaluate(
+                    &self.instrs,
+                    &mut self.stack,
+                    |offset| bits[(i as i128 + offset) as usize],
+                );
+                next[i] = table.bitwise_lookup(false, false);
+            }
+            mem::swap(&mut bits, &mut next);
+        }
+
+        let quarter = bits.len() / 4;
+        let positions: Vec<u128> = (0..(2 * quarter))
+            .filter(|&i| bits[quarter + i])
+            .map(|i| i as u128)
+            .collect();
+        self.build(&positions, self.base_level - 1)
+    }
+
+    /// Append a node's bits in ascending address order.
+    fn read_bits(&self, node: NodeId, bits: &mut Vec<bool>) {
+        match self.kind(node) {
+            NodeKind::Leaf(word) => {
+                bits.extend((0..64).map(|i| word & (0x1 << i) != 0));
+            }
+            NodeKind::Branch(left, right) => {
+                self.read_bits(left, bits);
+                self.read_bits(right, bits);
+            }
+        }
+    }
+
+    /// Set the bits of a node within memory, skipping empty regions.
+    fn write_to<S: BitStore>(&self, node: NodeId, origin: i128, memory: &mut S) {
+        let level = self.level(node);
+        if self.empty.get((level - LEAF_LEVEL) as usize) == Some(&node) {
+            return;
+        }
+        match self.kind(node) {
+            NodeKind::Leaf(word) => {
+                for i in 0..64 {
+                    if word & (0x1 << i) != 0 {
+                        memory.set_bit(origin + i as i128, true);
+                    }
+                }
+            }
+            NodeKind::Branch(left, right) => {
+                self.write_to(left, origin, memory);
+                self.write_to(right, origin + (0x1 << (level - 1)), memory);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::code::bytecode::compile::compile;
+    use crate::memory::Memory;
+    use crate::runtime::Runtime;
+    use crate::runtime::io::BufferIo;
+
+    /// Check that advancing gives the same memory as ticking one at a time.
+    fn check_against_ticks(code: &str, ticks: &[u64]) {
+        let program = compile(code).unwrap();
+        let mut hashlife = Hashlife::new(&program).unwrap();
+        let start = Runtime::new(program.clone(), BufferIo::new()).unwrap().memory().clone();
+
+        for &n in ticks {
+            let mut runtime = Runtime::new(program.clone(), BufferIo::new()).unwrap();
+            runtime.run_ticks(n).unwrap();
+
+            let advanced: Memory = hashlife.advance(&start, n).unwrap();
+            assert!(advanced == *runtime.memory(), "{} differs after {} ticks", code, n);
+        }
+    }
+
+    #[test]
+    fn matches_ticking() {
+        let ticks = [0, 1, 2, 3, 5, 17, 64, 100, 333];
+        check_against_ticks("1: <1", &ticks);
+        check_against_ticks("b: <2", &ticks);
+        check_against_ticks("5: | <1 >1", &ticks);
+        check_against_ticks("1: ^ <1 >1", &ticks);
+        check_against_ticks("d3: ^ <1 >2", &ticks);
+        check_against_ticks("1: ^ <5 >3", &ticks);
+        check_against_ticks("5: & | | <1 * >1 ~ & & <1 * >1", &ticks);
+    }
+
+    #[test]
+    fn rejects_unsupported() {
+        let unsupported = |code| Hashlife::new(&compile(code).unwrap()).err();
+        assert_eq!(unsupported("1: ^ <1 I"), Some(Unsupported::Io));
+        assert_eq!(unsupported("1: ~<1"), Some(Unsupported::Unstable));
+    }
+}
@@ -0,0 +1,52 @@
+use std::collections::VecDeque;
+
+/// Bit-stream I/O, as seen by a running program.
+pub trait Io {
+    /// Read a single bit from the input stream.
+    fn input(&mut self) -> bool;
+
+    /// Write a single bit to the output stream.
+    fn output(&mut self, bit: bool);
+}
+
+impl<T: Io + ?Sized> Io for &mut T {
+    fn input(&mut self) -> bool {
+        T::input(*self)
+    }
+
+    fn output(&mut self, bit: bool) {
+        T::output(*self, bit)
+    }
+}
+
+/// In-memory I/O, for embedding and testing.
+///
+/// Once the queued input is exhausted, further input yields no.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct BufferIo {
+    pub input: VecDeque<bool>,
+    pub output: Vec<bool>,
+}
+
+impl BufferIo {
+    pub fn new() -> Self {
+        BufferIo::default()
+    }
+
+    pub fn with_input<I: IntoIterator<Item=bool>>(input: I) -> Self {
+        BufferIo {
+            input: input.into_iter().collect(),
+            output: Vec::new(),
+        }
+    }
+}
+
+impl Io for BufferIo {
+    fn input(&mut self) -> bool {
+        self.input.pop_front().unwrap_or(false)
+    }
+
+    fn output(&mut self, bit: bool) {
+        self.output.push(bit);
+    }
+}
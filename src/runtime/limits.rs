@@ -0,0 +1,36 @@
+use std::time::Duration;
+
+/// Budgets on the resources a program may consume.
+///
+/// Each limit is unbounded if `None`.
+///
+/// Memory is only measured after each tick, so the page and byte limits may
+/// be overshot by what a single tick allocates. Heap bytes count allocated
+/// capacity, which stores may grow by doubling, so the byte limit may be
+/// crossed by up to twice the memory in use.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct Limits {
+    /// Maximum ticks since the program was loaded.
+    pub max_ticks: Option<u64>,
+    /// Maximum pages of memory allocated, checked after each tick.
+    pub max_pages: Option<usize>,
+    /// Maximum heap bytes used by memory, and by the generations remembered
+    /// to detect periodicity, checked after each tick.
+    pub max_memory_bytes: Option<usize>,
+    /// Maximum bits awake within a single tick.
+    pub max_awake: Option<usize>,
+    /// Maximum wall-clock time spent executing ticks, checked between ticks.
+    pub max_duration: Option<Duration>,
+}
+
+impl Limits {
+    /// No limits.
+    pub fn unlimited() -> Self {
+        Limits::default()
+    }
+}
+
+/// Whether `value` exceeds an optional limit.
+pub fn exceeds<T: PartialOrd>(value: T, limit: Option<T>) -> bool {
+    limit.map(|limit| value > limit).unwrap_or(false)
+}
@@ -0,0 +1,1112 @@
+/// Behavior rule evaluation.
+pub mod eval;
+
+/// Bit-stream I/O.
+pub mod io;
+
+/// Resource limits.
+pub mod limits;
+
+/// Runtime errors.
+pub mod error;
+
+/// Periodic state detection.
+pub mod cycle;
+
+/// Translated periodic state detection.
+pub mod spaceship;
+
+/// Shape of the address space.
+pub mod topology;
+
+/// Memoized evaluation of stable, I/O-free programs.
+pub mod hashlife;
+
+use std::collections::BTreeSet;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::mem::size_of;
+use std::time::{Duration, Instant};
+
+use self::io::Io;
+use self::limits::{Limits, exceeds};
+use self::error::{Error, ErrorKind};
+use self::cycle::{Cycle, CycleDetector, State};
+use self::spaceship::{Spaceship, SpaceshipDetector};
+use self::topology::Topology;
+use crate::code::bytecode::*;
+use crate::code::bytecode::verify;
+use crate::code::truthtable::IoTruthTable;
+use crate::memory::Memory;
+use crate::memory::store::{BitStore, BitCursor};
+
+/// Running instance of a compiled program, storing its memory in some store.
+///
+/// Only bits near those which changed are evaluated, which matches evaluating
+/// every bit only if the program is stable over the background, though any
+/// program may be run; see `is_stable`.
+pub struct Runtime<I: Io, S: BitStore = Memory> {
+    program: CompiledProgram,
+    memory: S,
+    awake: BTreeSet<i128>,
+    topology: Topology,
+    io: I,
+    tick_count: u64,
+    /// Wall-clock time spent executing ticks, including detecting periodicity.
+    elapsed: Duration,
+    limits: Limits,
+    cycle_detector: Option<CycleDetector<Generation<S>>>,
+    /// Cycle found by the detector, if any.
+    cycle: Option<Cycle>,
+    spaceship_detector: Option<SpaceshipDetector<NormalizedGeneration>>,
+    /// Spaceship found by the detector, if any.
+    spaceship: Option<Spaceship>,
+    stack: Vec<IoTruthTable<u8>>,
+}
+
+/// Result of running for some number of ticks.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct Outcome {
+    /// Number of ticks executed.
+    pub ticks: u64,
+    /// Why execution stopped.
+    pub reason: StopReason,
+    /// Number of bits input.
+    pub inputs: u64,
+    /// Number of bits output.
+    pub outputs: u64,
+}
+
+/// Why execution stopped.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub enum StopReason {
+    /// The requested number of ticks were executed.
+    Ticks,
+    /// The stopping predicate was satisfied.
+    Predicate,
+    /// No bits are awake, so the program can never change again.
+    Quiescent,
+    /// A previous state was repeated, so the program is periodic.
+    Periodic(Cycle),
+    /// A previous state was repeated up to translation, so the program is a
+    /// spaceship.
+    Translated(Spaceship),
+}
+
+/// Counts of I/O performed in a single tick.
+#[derive(Copy, Clone, Debug, Default)]
+struct TickIo {
+    inputs: u64,
+    outputs: u64,
+}
+
+/// Full state of a generation, kept to confirm repeats found by hash.
+#[derive(Clone, Eq, PartialEq, Debug)]
+struct Generation<S> {
+    memory: S,
+    awake: BTreeSet<i128>,
+}
+
+/// State of a generation relative to its lowest set address, equal for
+/// generations which are translations of each other.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+struct NormalizedGeneration {
+    /// Offset of the lowest set address within the background's period.
+    phase: i128,
+    ones: Vec<i128>,
+    awake: Vec<i128>,
+}
+
+impl<S: BitStore> State for Generation<S> {
+    fn heap_bytes(&self) -> usize {
+        self.memory.heap_bytes() + self.awake.len() * size_of::<i128>()
+    }
+}
+
+impl State for NormalizedGeneration {
+    fn heap_bytes(&self) -> usize {
+        (self.ones.capacity() + self.awake.capacity()) * size_of::<i128>()
+    }
+}
+
+/// Evaluated bit, pending its I/O and write.
+struct Pending {
+    address: i128,
+    table: IoTruthTable<u8>,
+    output: bool,
+    input: Option<bool>,
+}
+
+impl<I: Io> Runtime<I> {
+    /// Load a program, with its activation pattern starting at address 0.
+    ///
+    /// Fails if the program is malformed, locating the error in its source
+    /// code if it has debug info.
+    pub fn new(program: CompiledProgram, io: I) -> Result<Self, verify::Error> {
+        Runtime::with_store(program, io, Memory::new())
+    }
+}
+
+// errors carry their outcome inline, since they end a run, so are rare
+#[allow(clippy::result_large_err)]
+impl<I: Io, S: BitStore> Runtime<I, S> {
+    /// Load a program into some store, with its activation pattern written
+    /// over it starting at address 0.
+    ///
+    /// Bits of the activation pattern which differ from the store's
+    /// background start awake.
+    ///
+    /// Fails if the program is malformed, as for `new`.
+    pub fn with_store(program: CompiledProgram, io: I, mut memory: S) -> Result<Self, verify::Error> {
+        let stack_capacity = verify::verify_program(&program)?.max_depth;
+
+        memory.write_range(0, &program.activation);
+        let awake: BTreeSet<i128> = (0..).zip(program.activation.iter())
+            .filter(|&(address, &bit)| bit != memory.background().get(address))
+            .map(|(address, _)| address)
+            .collect();
+
+        Ok(Runtime {
+            program,
+            memory,
+            awake,
+            topology: Topology::Unbounded,
+            io,
+            tick_count: 0,
+            elapsed: Duration::from_secs(0),
+            limits: Limits::unlimited(),
+            cycle_detector: None,
+            cycle: None,
+            spaceship_detector: None,
+            spaceship: None,
+            stack: Vec::with_capacity(stack_capacity),
+        })
+    }
+
+    pub fn program(&self) -> &CompiledProgram {
+        &self.program
+    }
+
+    pub fn memory(&self) -> &S {
+        &self.memory
+    }
+
+    pub fn io(&self) -> &I {
+        &self.io
+    }
+
+    pub fn io_mut(&mut self) -> &mut I {
+        &mut self.io
+    }
+
+    pub fn into_io(self) -> I {
+        self.io
+    }
+
+    /// Number of ticks executed since the program was loaded.
+    pub fn tick_count(&self) -> u64 {
+        self.tick_count
+    }
+
+    /// Wall-clock time spent executing ticks since the program was loaded,
+    /// including detecting periodicity after each.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    pub fn topology(&self) -> Topology {
+        self.topology
+    }
+
+    /// Set the shape of the address space.
+    ///
+    /// Set and awake bits outside a ring are wrapped onto it, merging with
+    /// any bits already there. Periodicity detection restarts from the new
+    /// state.
+    ///
+    /// Panics if a ring's length is zero, greater than `i128::MAX`, or not a
+    /// multiple of the period of the background.
+    pub fn set_topology(&mut self, topology: Topology) {
+        if let Topology::Ring(len) = topology {
+            assert!(len > 0 && len <= i128::MAX as u128, "invalid ring length {}", len);
+            assert!(
+                len.is_multiple_of(self.memory.background().period() as u128),
+                "ring length {} is not a multiple of the background period", len,
+            );
+        }
+        self.topology = topology;
+
+        if !self.memory.ones().all(|address| topology.contains(address)) {
+            self.memory = self.remapped(|address| topology.wrap(address));
+        }
+        self.awake = self.awake.iter().map(|&address| topology.wrap(address)).collect();
+        self.restart_detection();
+    }
+
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Set the resource limits.
+    ///
+    /// The tick limit is checked before each tick, and the awake limit before
+    /// any bit of a tick is evaluated, so these are never exceeded. The time
+    /// limit is checked only between ticks, and the page and memory limits
+    /// only after each tick, so these are approximate: the tick which crosses
+    /// one completes first.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Enable or disable cycle detection.
+    ///
+    /// While enabled, every generation is remembered, and execution stops the
+    /// first time a previous state repeats. Only the latest
+    /// `cycle::MAX_GENERATIONS` are remembered, which count against the
+    /// memory limit.
+    pub fn set_cycle_detection(&mut self, enabled: bool) {
+        if enabled {
+            if self.cycle_detector.is_none() {
+                let mut detector = CycleDetector::new();
+                detector.record(self.tick_count, self.generation_hash(), self.generation());
+                self.cycle_detector = Some(detector);
+            }
+        } else {
+            self.cycle_detector = None;
+            self.cycle = None;
+        }
+    }
+
+    /// The cycle the program has been detected to be in, if any.
+    pub fn cycle(&self) -> Option<Cycle> {
+        self.cycle
+    }
+
+    /// Hash of the current state of memory and of which bits are awake.
+    pub fn generation_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.memory.hash(&mut hasher);
+        for address in &self.awake {
+            address.hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    /// Enable or disable spaceship detection.
+    ///
+    /// While enabled, every generation is remembered relative to its lowest
+    /// set address, and execution stops the first time a previous state
+    /// repeats up to translation. As for cycle detection, the generations
+    /// remembered are bounded, and count against the memory limit.
+    pub fn set_spaceship_detection(&mut self, enabled: bool) {
+        if enabled {
+            if self.spaceship_detector.is_none() {
+                let mut detector = SpaceshipDetector::new();
+                let (generation, origin) = self.normalized_generation();
+                let hash = self.normalized_hash(&generation);
+                detector.record(self.tick_count, hash, generation, origin, 0);
+                self.spaceship_detector = Some(detector);
+            }
+        } else {
+            self.spaceship_detector = None;
+            self.spaceship = None;
+        }
+    }
+
+    /// The spaceship the program has been detected to be, if any.
+    pub fn spaceship(&self) -> Option<Spaceship> {
+        self.spaceship
+    }
+
+    /// Hash of the current state of memory and of which bits are awake,
+    /// relative to the lowest set address, which is also returned.
+    ///
+    /// With a background, it is relative to the lowest address differing from
+    /// the background, and states only match when translated by a multiple
+    /// of its period. If no bits are set, the origin is 0.
+    pub fn normalized_generation_hash(&self) -> (u64, i128) {
+        let (generation, origin) = self.normalized_generation();
+        (self.normalized_hash(&generation), origin)
+    }
+
+    /// Advance a detected spaceship by some number of its periods at once,
+    /// by translating memory rather than executing ticks.
+    ///
+    /// Returns false without changing anything if no spaceship has been
+    /// detected, if it performs output, which would be skipped, if it would
+    /// move beyond the address space, or if the tick count would exceed the
+    /// tick limit.
+    pub fn fast_forward(&mut self, periods: u64) -> bool {
+        let spaceship = match self.spaceship {
+            Some(spaceship) if spaceship.outputs == 0 => spaceship,
+            _ => return false,
+        };
+        let shift = match spaceship.displacement.checked_mul(periods as i128) {
+            Some(shift) => shift,
+            None => return false,
+        };
+        let tick_count = match spaceship.period.checked_mul(periods)
+            .and_then(|ticks| self.tick_count.checked_add(ticks)) {
+            Some(tick_count) => tick_count,
+            None => return false,
+        };
+        if exceeds(tick_count, self.limits.max_ticks) {
+            return false;
+        }
+
+        if let Topology::Ring(_) = self.topology {
+            // rotate around the ring
+            let topology = self.topology;
+            self.memory = self.remapped(|address| topology.add(address, shift).unwrap());
+        } else {
+            let in_range = |address: &i128| address.checked_add(shift).is_some();
+            let bounds = self.memory.bounds();
+            if !bounds.iter().all(|(low, high)| in_range(low) && in_range(high))
+                || !self.awake.iter().all(in_range) {
+                return false;
+            }
+
+            self.memory = self.memory.shifted(shift);
+        }
+        let topology = self.topology;
+        self.awake = self.awake.iter().map(|&address| topology.add(address, shift).unwrap()).collect();
+        self.tick_count = tick_count;
+        true
+    }
+
+    /// Whether memory nobody writes never changes, nor performs I/O.
+    ///
+    /// This is not checked when running. Unstable programs still run, but
+    /// bits of untouched memory stay asleep, so neither change nor perform
+    /// I/O as evaluating them would have them do.
+    pub fn is_stable(&self) -> bool {
+        eval::is_stable(self.program.instrs(), self.memory.background())
+    }
+
+    /// Addresses of the bits which are currently awake, in ascending order.
+    pub fn awake(&self) -> impl Iterator<Item=i128> + '_ {
+        self.awake.iter().cloned()
+    }
+
+    /// Whether no bits are awake, meaning the program can never change again.
+    pub fn is_quiescent(&self) -> bool {
+        self.awake.is_empty()
+    }
+
+    /// Overwrite a bit of memory from outside the program, waking it up.
+    ///
+    /// On a ring, the address is wrapped onto it. Periodicity detection
+    /// restarts from the new state.
+    pub fn set_bit(&mut self, address: i128, bit: bool) {
+        let address = self.topology.wrap(address);
+        self.memory.set_bit(address, bit);
+        self.awake.insert(address);
+        self.restart_detection();
+    }
+
+    /// Execute a single tick, unless quiescent.
+    pub fn step(&mut self) -> Result<Outcome, Error> {
+        self.run_ticks(1)
+    }
+
+    /// Execute up to `n` ticks, stopping early if quiescent.
+    pub fn run_ticks(&mut self, n: u64) -> Result<Outcome, Error> {
+        let mut outcome = Outcome::new();
+        while outcome.ticks < n {
+            if self.is_quiescent() {
+                outcome.reason = StopReason::Quiescent;
+                return Ok(outcome);
+            }
+            if let Some(reason) = self.limited_tick(&mut outcome)? {
+                outcome.reason = reason;
+                return Ok(outcome);
+            }
+        }
+        outcome.reason = StopReason::Ticks;
+        Ok(outcome)
+    }
+
+    /// Execute ticks until `predicate` is satisfied, or quiescent.
+    ///
+    /// The predicate is checked before each tick, so no ticks are executed
+    /// if it is already satisfied.
+    pub fn run_until<F>(&mut self, mut predicate: F) -> Result<Outcome, Error>
+        where F: FnMut(&Self) -> bool
+    {
+        let mut outcome = Outcome::new();
+        loop {
+            if predicate(self) {
+                outcome.reason = StopReason::Predicate;
+                return Ok(outcome);
+            }
+            if self.is_quiescent() {
+                outcome.reason = StopReason::Quiescent;
+                return Ok(outcome);
+            }
+            if let Some(reason) = self.limited_tick(&mut outcome)? {
+                outcome.reason = reason;
+                return Ok(outcome);
+            }
+        }
+    }
+
+    /// Execute ticks until quiescent. May never return, unless limited.
+    pub fn run(&mut self) -> Result<Outcome, Error> {
+        self.run_until(|_| false)
+    }
+
+    /// Execute a single tick within the resource limits, recording it, and
+    /// returning the reason to stop if periodicity is newly detected.
+    fn limited_tick(&mut self, outcome: &mut Outcome) -> Result<Option<StopReason>, Error> {
+        let error = |kind, message, outcome: &Outcome| Error {
+            message,
+            kind,
+            outcome: *outcome,
+        };
+
+        if exceeds(self.tick_count + 1, self.limits.max_ticks) {
+            return Err(error(
+                ErrorKind::TickLimit,
+                format!("exceeded limit of {} ticks", self.limits.max_ticks.unwrap()),
+                outcome,
+            ));
+        }
+        if exceeds(self.elapsed, self.limits.max_duration) {
+            return Err(error(
+                ErrorKind::TimeLimit,
+                format!("exceeded time limit after {:?}", self.elapsed),
+                outcome,
+            ));
+        }
+
+        let start = Instant::now();
+        let tick_io = match self.tick() {
+            Ok(tick_io) => tick_io,
+            Err(awake) => {
+                self.elapsed += start.elapsed();
+                return Err(error(
+                    ErrorKind::AwakeLimit,
+                    format!("{} bits awake, exceeding limit", awake),
+                    outcome,
+                ));
+            }
+        };
+        outcome.record(tick_io);
+        let periodicity = self.detect_periodicity(tick_io);
+        self.elapsed += start.elapsed();
+
+        if exceeds(self.memory.page_count(), self.limits.max_pages) {
+            return Err(error(
+                ErrorKind::PageLimit,
+                format!("{} pages allocated, exceeding limit", self.memory.page_count()),
+                outcome,
+            ));
+        }
+        if exceeds(self.heap_bytes(), self.limits.max_memory_bytes) {
+            return Err(error(
+                ErrorKind::MemoryLimit,
+                format!("{} bytes allocated, exceeding limit", self.heap_bytes()),
+                outcome,
+            ));
+        }
+
+        Ok(periodicity)
+    }
+
+    /// Number of heap bytes allocated for memory, and for the generations
+    /// remembered by the periodicity detectors.
+    fn heap_bytes(&self) -> usize {
+        self.memory.heap_bytes()
+            + self.cycle_detector.as_ref().map_or(0, CycleDetector::heap_bytes)
+            + self.spaceship_detector.as_ref().map_or(0, SpaceshipDetector::heap_bytes)
+    }
+
+    /// Execute a single tick.
+    ///
+    /// If too many bits would be awake, fails with that number, without
+    /// changing any state.
+    fn tick(&mut self) -> Result<TickIo, usize> {
+        // wake up every bit listening to an awake bit
+        let mut woken: BTreeSet<i128> = self.awake.clone();
+        for &address in &self.awake {
+            for &offset in &self.program.metadata().offsets {
+                if let Some(listener) = self.topology.sub(address, offset) {
+                    woken.insert(listener);
+                }
+            }
+            if exceeds(woken.len(), self.limits.max_awake) {
+                return Err(woken.len());
+            }
+        }
+
+        // evaluate every awake bit against the previous state of memory
+        let mut pending: Vec<Pending> = Vec::with_capacity(woken.len());
+        let topology = self.topology;
+        let mut cursor = self.memory.cursor(0);
+        for address in woken {
+            let table = eval::evaluate(
+                self.program.instrs(),
+                &mut self.stack,
+                |offset| topology.add(address, offset).map(|read| cursor.get(read)).unwrap_or(false),
+            );
+            pending.push(Pending {
+                address,
+                table,
+                output: false,
+                input: None,
+            });
+        }
+        drop(cursor);
+
+        let mut tick_io = TickIo::default();
+
+        // first all outputs are performed, left-to-right
+        for bit in &mut pending {
+            if eval::does_output(bit.table) {
+                self.io.output(self.memory.get_bit(bit.address));
+                bit.output = true;
+                tick_io.outputs += 1;
+            }
+        }
+
+        // then all inputs are performed, left-to-right
+        for bit in &mut pending {
+            if eval::does_input(bit.table, bit.output) {
+                bit.input = Some(self.io.input());
+                tick_io.inputs += 1;
+            }
+        }
+
+        // write results, and put bits which did nothing back to sleep
+        self.awake.clear();
+        for bit in pending {
+            let value = bit.table.bitwise_lookup(bit.input.unwrap_or(false), bit.output);
+            let changed = value != self.memory.get_bit(bit.address);
+            if changed {
+                self.memory.set_bit(bit.address, value);
+            }
+            if changed || bit.output || bit.input.is_some() {
+                self.awake.insert(bit.address);
+            }
+        }
+
+        self.tick_count += 1;
+        Ok(tick_io)
+    }
+
+    /// Forget every earlier generation, and any cycle or spaceship found, as
+    /// the state has been changed from outside the program.
+    fn restart_detection(&mut self) {
+        let cycle_detection = self.cycle_detector.is_some();
+        self.set_cycle_detection(false);
+        self.set_cycle_detection(cycle_detection);
+        let spaceship_detection = self.spaceship_detector.is_some();
+        self.set_spaceship_detection(false);
+        self.set_spaceship_detection(spaceship_detection);
+    }
+
+    /// Copy of the current state, for comparing with later generations.
+    fn generation(&self) -> Generation<S> {
+        Generation {
+            memory: self.memory.clone(),
+            awake: self.awake.clone(),
+        }
+    }
+
+    /// The current state relative to the lowest set address, which is also
+    /// returned.
+    fn normalized_generation(&self) -> (NormalizedGeneration, i128) {
+        let origin = self.memory.bounds().map(|(low, _)| low).unwrap_or(0);
+        let generation = NormalizedGeneration {
+            phase: origin.rem_euclid(self.memory.background().period() as i128),
+            ones: self.memory.ones().map(|address| address.wrapping_sub(origin)).collect(),
+            awake: self.awake.iter().map(|address| address.wrapping_sub(origin)).collect(),
+        };
+        (generation, origin)
+    }
+
+    fn normalized_hash(&self, generation: &NormalizedGeneration) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.memory.background().hash(&mut hasher);
+        generation.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Copy of memory with each bit differing from the background moved to
+    /// another address, merging any which land together.
+    fn remapped<F: Fn(i128) -> i128>(&self, remap: F) -> S {
+        let background = self.memory.background();
+        let mut memory = S::with_background(background.clone());
+        for address in self.memory.ones() {
+            let address = remap(address);
+            memory.set_bit(address, !background.get(address));
+        }
+        memory
+    }
+
+    /// Record the new generation with the enabled detectors, returning the
+    /// reason to stop if one newly detects periodicity.
+    fn detect_periodicity(&mut self, tick_io: TickIo) -> Option<StopReason> {
+        let cycle = self.detect_cycle(tick_io);
+        let spaceship = self.detect_spaceship(tick_io);
+
+        cycle.map(StopReason::Periodic)
+            .or_else(|| spaceship.map(StopReason::Translated))
+    }
+
+    /// Record the new generation with the cycle detector, if enabled,
+    /// returning a newly detected cycle.
+    fn detect_cycle(&mut self, tick_io: TickIo) -> Option<Cycle> {
+        let mut detector = self.cycle_detector.take()?;
+        let hash = self.generation_hash();
+        let generation = self.generation();
+
+        // input breaks the determinism which periodicity relies on
+        if tick_io.inputs > 0 {
+            detector.clear();
+            self.cycle = None;
+        }
+
+        let found = detector.record(self.tick_count, hash, generation);
+        self.cycle_detector = Some(detector);
+
+        if self.cycle.is_none() && found.is_some() {
+            self.cycle = found;
+            found
+        } else {
+            None
+        }
+    }
+
+    /// Record the new generation with the spaceship detector, if enabled,
+    /// returning a newly detected spaceship.
+    fn detect_spaceship(&mut self, tick_io: TickIo) -> Option<Spaceship> {
+        let mut detector = self.spaceship_detector.take()?;
+        let (generation, origin) = self.normalized_generation();
+        let hash = self.normalized_hash(&generation);
+
+        // input breaks the determinism which periodicity relies on
+        if tick_io.inputs > 0 {
+            detector.clear();
+            self.spaceship = None;
+        }
+
+        let found = detector.record(self.tick_count, hash, generation, origin, tick_io.outputs);
+        self.spaceship_detector = Some(detector);
+
+        if self.spaceship.is_none() && found.is_some() {
+            self.spaceship = found;
+            found
+        } else {
+            None
+        }
+    }
+}
+
+impl Outcome {
+    fn new() -> Self {
+        Outcome {
+            ticks: 0,
+            reason: StopReason::Ticks,
+            inputs: 0,
+            outputs: 0,
+        }
+    }
+
+    fn record(&mut self, tick_io: TickIo) {
+        self.ticks += 1;
+        self.inputs += tick_io.inputs;
+        self.outputs += tick_io.outputs;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::io::BufferIo;
+    use crate::code::bytecode::compile::compile;
+    use crate::memory::background::Background;
+
+    fn runtime(code: &str) -> Runtime<BufferIo> {
+        Runtime::new(compile(code).unwrap(), BufferIo::new()).unwrap()
+    }
+
+    /// I/O recording each bit as `('o', bit)` or `('i', bit)`, in order.
+    #[derive(Default)]
+    struct LogIo {
+        input: Vec<bool>,
+        log: Vec<(char, bool)>,
+    }
+
+    impl Io for LogIo {
+        fn input(&mut self) -> bool {
+            let bit = !self.input.is_empty() && self.input.remove(0);
+            self.log.push(('i', bit));
+            bit
+        }
+
+        fn output(&mut self, bit: bool) {
+            self.log.push(('o', bit));
+        }
+    }
+
+    #[test]
+    fn stops_when_quiescent() {
+        // a lone bit dies, and then its neighborhood settles
+        let mut runtime = runtime("1: & <1 >1");
+        let outcome = runtime.run_ticks(10).unwrap();
+        assert_eq!(outcome.reason, StopReason::Quiescent);
+        assert_eq!(outcome.ticks, 2);
+        assert!(runtime.is_quiescent());
+        assert_eq!(runtime.memory().popcount(), 0);
+
+        // nothing is left to execute
+        let outcome = runtime.step().unwrap();
+        assert_eq!((outcome.reason, outcome.ticks), (StopReason::Quiescent, 0));
+        assert_eq!(runtime.run().unwrap().ticks, 0);
+    }
+
+    #[test]
+    fn runs_ticks_and_until_predicate() {
+        let mut runtime = runtime("1: <1");
+        let outcome = runtime.run_ticks(3).unwrap();
+        assert_eq!((outcome.reason, outcome.ticks), (StopReason::Ticks, 3));
+        assert_eq!(runtime.memory().ones().collect::<Vec<_>>(), vec![3]);
+
+        let outcome = runtime.step().unwrap();
+        assert_eq!((outcome.reason, outcome.ticks), (StopReason::Ticks, 1));
+
+        // the predicate is checked before each tick
+        let outcome = runtime.run_until(|runtime| runtime.memory().get_bit(10)).unwrap();
+        assert_eq!((outcome.reason, outcome.ticks), (StopReason::Predicate, 6));
+        assert_eq!(runtime.tick_count(), 10);
+        let outcome = runtime.run_until(|runtime| runtime.memory().get_bit(10)).unwrap();
+        assert_eq!((outcome.reason, outcome.ticks), (StopReason::Predicate, 0));
+    }
+
+    #[test]
+    fn counts_io() {
+        let program = compile("1: ^ ^ O I <1").unwrap();
+        let io = BufferIo::with_input(vec![true; 3]);
+        let mut runtime = Runtime::new(program, io).unwrap();
+
+        // bits 0 and 1 are awake, the latter listening to the former
+        let outcome = runtime.step().unwrap();
+        assert_eq!((outcome.ticks, outcome.inputs, outcome.outputs), (1, 2, 2));
+        assert_eq!(runtime.io().output, vec![true, false]);
+
+        let outcome = runtime.run_ticks(3).unwrap();
+        assert_eq!(outcome.ticks, 3);
+        assert_eq!(outcome.outputs, runtime.io().output.len() as u64 - 2);
+        assert_eq!(outcome.inputs, outcome.outputs);
+        assert!(runtime.io().input.is_empty());
+    }
+
+    #[test]
+    fn outputs_before_inputs_left_to_right() {
+        let program = compile("1: ^ ^ O I <1").unwrap();
+        let io = LogIo {
+            input: vec![true, false],
+            log: Vec::new(),
+        };
+        let mut runtime = Runtime::new(program, io).unwrap();
+        runtime.step().unwrap();
+
+        // bit 0 then bit 1 output, then bit 0 then bit 1 input
+        assert_eq!(runtime.io().log, vec![('o', true), ('o', false), ('i', true), ('i', false)]);
+        // each bit becomes whether it output, xor its input, xor its left
+        // neighbor, so had the inputs been swapped, both would be set
+        assert_eq!(runtime.memory().popcount(), 0);
+    }
+
+    #[test]
+    fn rejects_malformed_programs() {
+        let program = compile("1: ^ <1 >1").unwrap();
+        let mut instrs = program.instrs().to_vec();
+        instrs.remove(0);
+        let program = CompiledProgram::new(program.activation, instrs, program.debug_info);
+        let error = Runtime::new(program, BufferIo::new()).err().unwrap();
+        assert_eq!(error.kind, verify::ErrorKind::Underflow);
+        assert!(error.source.is_some());
+    }
+
+    #[test]
+    fn stability_requires_no_io() {
+        assert!(runtime("1: <1").is_stable());
+        assert!(!runtime("1: ~<1").is_stable());
+        assert!(!runtime("1: ^ <1 I").is_stable());
+        assert!(!runtime("1: ^ <1 O").is_stable());
+        // I/O whose result cannot matter is never performed
+        assert!(runtime("1: & <1 I").is_stable());
+
+        let background = Background::new(&[true, false]);
+        let memory = Memory::with_background(background);
+        let with_store = |code| Runtime::with_store(compile(code).unwrap(), BufferIo::new(), memory.clone()).unwrap();
+        assert!(with_store("1: <2").is_stable());
+        assert!(!with_store("1: <1").is_stable());
+    }
+
+    #[test]
+    fn tick_limit_stops_cleanly() {
+        let mut runtime = runtime("1: <1");
+        runtime.set_limits(Limits {
+            max_ticks: Some(5),
+            ..Limits::unlimited()
+        });
+
+        let error = runtime.run().unwrap_err();
+        assert_eq!(error.kind, ErrorKind::TickLimit);
+        assert_eq!(error.outcome.ticks, 5);
+        assert_eq!(error.to_string(), error.message);
+        assert_eq!(runtime.tick_count(), 5);
+    }
+
+    #[test]
+    fn awake_limit_stops_before_tick() {
+        let mut runtime = runtime("1: | <1 >1");
+        runtime.set_limits(Limits {
+            max_awake: Some(4),
+            ..Limits::unlimited()
+        });
+
+        let error = runtime.run().unwrap_err();
+        assert_eq!(error.kind, ErrorKind::AwakeLimit);
+        assert_eq!(runtime.tick_count(), error.outcome.ticks);
+        assert!(runtime.awake().count() <= 4);
+    }
+
+    #[test]
+    fn page_limit_stops_after_tick() {
+        // each bit spreads across into the page before its own, pages
+        // holding 8192 bits
+        let mut runtime = runtime("1: | <1 >1");
+        runtime.set_bit(5 * 8192, true);
+        assert_eq!(runtime.memory().page_count(), 2);
+        runtime.set_limits(Limits {
+            max_pages: Some(3),
+            ..Limits::unlimited()
+        });
+
+        let error = runtime.run().unwrap_err();
+        assert_eq!(error.kind, ErrorKind::PageLimit);
+        assert_eq!(error.outcome.ticks, 1);
+        assert_eq!(runtime.memory().page_count(), 4);
+    }
+
+    #[test]
+    fn memory_limit_stops_after_tick() {
+        let mut runtime = runtime("1: | <1 >1");
+        runtime.set_limits(Limits {
+            max_memory_bytes: Some(1),
+            ..Limits::unlimited()
+        });
+
+        // the tick which crosses the limit completes
+        let error = runtime.run().unwrap_err();
+        assert_eq!(error.kind, ErrorKind::MemoryLimit);
+        assert_eq!(error.outcome.ticks, 1);
+        assert_eq!(runtime.memory().popcount(), 2);
+    }
+
+    #[test]
+    fn time_limit_checked_between_ticks() {
+        let mut runtime = runtime("1: | <1 >1");
+        runtime.set_limits(Limits {
+            max_duration: Some(Duration::from_secs(0)),
+            ..Limits::unlimited()
+        });
+
+        // no time has been spent before the first tick, which overshoots
+        let error = runtime.run().unwrap_err();
+        assert_eq!(error.kind, ErrorKind::TimeLimit);
+        assert_eq!(error.outcome.ticks, 1);
+        assert!(runtime.elapsed() > Duration::from_secs(0));
+    }
+
+    #[test]
+    fn moving_pattern_keeps_pages_bounded() {
+        // pages hold 8192 bits, so this crosses several
+        let mut runtime = runtime("d: ^ <2 & <1 >1");
+        for _ in 0..8 {
+            runtime.run_ticks(2048).unwrap();
+            assert!(runtime.memory().page_count() <= 2);
+        }
+        assert!(runtime.memory().bounds().unwrap().0 > 3 * 8192);
+    }
+
+    #[test]
+    fn detects_cycle() {
+        let mut runtime = runtime("1: ~ *");
+        runtime.set_cycle_detection(true);
+
+        let outcome = runtime.run_ticks(100).unwrap();
+        assert_eq!(outcome.reason, StopReason::Periodic(Cycle { start: 0, period: 2 }));
+    }
+
+    #[test]
+    fn detects_spaceship() {
+        let mut growing = runtime("5: | <1 >1");
+        growing.set_spaceship_detection(true);
+        growing.run_ticks(100).unwrap();
+        assert_eq!(growing.spaceship(), None);
+
+        let mut moving = runtime("1: <1");
+        moving.set_spaceship_detection(true);
+        let outcome = moving.run_ticks(100).unwrap();
+        match outcome.reason {
+            StopReason::Translated(spaceship) => {
+                assert_eq!((spaceship.period, spaceship.displacement), (1, 1));
+            }
+            reason => panic!("expected a spaceship, stopped by {:?}", reason),
+        }
+    }
+
+    #[test]
+    fn detection_history_counts_against_memory_limit() {
+        // a spaceship never repeats exactly, so the cycle detector remembers
+        // every generation
+        let mut moving = runtime("1: <1");
+        let live_bytes = moving.memory().heap_bytes();
+        moving.set_limits(Limits {
+            max_memory_bytes: Some(live_bytes * 10),
+            ..Limits::unlimited()
+        });
+        moving.set_cycle_detection(true);
+
+        let error = moving.run_ticks(1000).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::MemoryLimit);
+        assert!(error.outcome.ticks < 10);
+        assert!(moving.memory().heap_bytes() <= live_bytes * 10);
+
+        // likewise for the spaceship detector, with a pattern which grows
+        let mut growing = runtime("1: | <1 >1");
+        growing.set_limits(Limits {
+            max_memory_bytes: Some(0x10000),
+            ..Limits::unlimited()
+        });
+        growing.set_spaceship_detection(true);
+
+        let error = growing.run_ticks(10_000).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::MemoryLimit);
+        assert!(growing.memory().heap_bytes() < 0x10000);
+    }
+
+    #[test]
+    fn fast_forward_matches_ticking() {
+        let code = "d: ^ <2 & <1 >1";
+        let mut skipped = runtime(code);
+        skipped.set_spaceship_detection(true);
+        skipped.run_ticks(100).unwrap();
+        let spaceship = skipped.spaceship().unwrap();
+        assert_eq!((spaceship.period, spaceship.displacement), (2, 4));
+
+        let mut ticked = runtime(code);
+        ticked.run_ticks(skipped.tick_count() + 50 * spaceship.period).unwrap();
+        assert!(skipped.fast_forward(50));
+        assert_eq!(skipped.tick_count(), ticked.tick_count());
+        assert!(skipped.memory() == ticked.memory());
+        assert!(skipped.awake().eq(ticked.awake()));
+    }
+
+    #[test]
+    fn fast_forward_respects_tick_limit() {
+        let mut runtime = runtime("1: <1");
+        runtime.set_spaceship_detection(true);
+        runtime.run_ticks(10).unwrap();
+        let tick_count = runtime.tick_count();
+        runtime.set_limits(Limits {
+            max_ticks: Some(tick_count + 100),
+            ..Limits::unlimited()
+        });
+
+        assert!(!runtime.fast_forward(101));
+        assert!(!runtime.fast_forward(u64::MAX));
+        assert_eq!(runtime.tick_count(), tick_count);
+        assert!(runtime.fast_forward(100));
+        assert_eq!(runtime.tick_count(), tick_count + 100);
+
+        // the tick count itself must not overflow
+        runtime.set_limits(Limits::unlimited());
+        runtime.tick_count = u64::MAX - 1;
+        assert!(!runtime.fast_forward(2));
+        assert!(runtime.fast_forward(1));
+    }
+
+    #[test]
+    fn glider_wraps_around_ring() {
+        // moves 4 bits every 2 ticks, so goes once around in 10
+        let mut runtime = runtime("d: ^ <2 & <1 >1");
+        runtime.set_topology(Topology::Ring(20));
+        let start: Vec<i128> = runtime.memory().ones().collect();
+        for _ in 0..4 {
+            runtime.run_ticks(2).unwrap();
+            assert!(runtime.memory().ones().collect::<Vec<_>>() != start);
+            assert!(runtime.memory().ones().all(|address| (0..20).contains(&address)));
+        }
+        runtime.run_ticks(2).unwrap();
+        assert_eq!(runtime.memory().ones().collect::<Vec<_>>(), start);
+
+        runtime.set_cycle_detection(true);
+        let outcome = runtime.run_ticks(100).unwrap();
+        assert_eq!(outcome.reason, StopReason::Periodic(Cycle { start: 10, period: 10 }));
+    }
+
+    #[test]
+    fn set_topology_folds_bits_onto_ring() {
+        let mut runtime = runtime("1: <1");
+        runtime.set_bit(25, true);
+        runtime.set_bit(-3, true);
+        runtime.set_bit(30, true);
+        runtime.set_topology(Topology::Ring(10));
+
+        // 30 merges with the 0 already set
+        assert_eq!(runtime.memory().ones().collect::<Vec<_>>(), vec![0, 5, 7]);
+        assert_eq!(runtime.awake().collect::<Vec<_>>(), vec![0, 5, 7]);
+
+        // as do writes from outside
+        runtime.set_bit(-1, true);
+        assert!(runtime.memory().get_bit(9) && !runtime.memory().get_bit(-1));
+
+        runtime.run_ticks(1).unwrap();
+        assert_eq!(runtime.memory().ones().collect::<Vec<_>>(), vec![0, 1, 6, 8]);
+    }
+
+    #[test]
+    fn ring_io_runs_in_ascending_order_from_zero() {
+        let program = compile("1: ^ ^ O I <1").unwrap();
+        let io = LogIo {
+            input: vec![true, false, true, false, true],
+            log: Vec::new(),
+        };
+        let mut runtime = Runtime::new(program, io).unwrap();
+        runtime.set_bit(5, false);
+        runtime.set_bit(-1, true);
+        runtime.set_topology(Topology::Ring(10));
+        runtime.step().unwrap();
+
+        // bits 0, 1, 5, 6 and 9 are awake or listening, with 9 set where -1
+        // was, so it goes last
+        let outputs: Vec<bool> = runtime.io().log.iter()
+            .filter(|&&(kind, _)| kind == 'o')
+            .map(|&(_, bit)| bit)
+            .collect();
+        assert_eq!(outputs, vec![true, false, false, false, true]);
+        let inputs = runtime.io().log.iter().filter(|&&(kind, _)| kind == 'i').count();
+        assert_eq!(inputs, 5);
+        assert_eq!(runtime.io().log[..5].iter().filter(|&&(kind, _)| kind == 'o').count(), 5);
+    }
+
+    #[test]
+    fn external_write_restarts_detection() {
+        let mut runtime = runtime("1: <1");
+        runtime.set_cycle_detection(true);
+        runtime.set_spaceship_detection(true);
+        runtime.run_ticks(10).unwrap();
+        assert!(runtime.spaceship().is_some());
+
+        runtime.set_bit(-5, true);
+        assert_eq!(runtime.spaceship(), None);
+        assert_eq!(runtime.cycle(), None);
+        assert!(!runtime.fast_forward(10));
+    }
+}
@@ -0,0 +1,136 @@
+use super::cycle::{History, MAX_GENERATIONS, State};
+
+/// A state repeated up to translation, after which the program moves along
+/// the tape periodically.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct Spaceship {
+    /// Tick at which the repeated state first occurred, among those still
+    /// remembered.
+    pub start: u64,
+    /// Number of ticks between repetitions.
+    pub period: u64,
+    /// Distance moved along the tape each period, which is positive for
+    /// movement to the right.
+    pub displacement: i128,
+    /// Number of bits output each period.
+    pub outputs: u64,
+}
+
+impl Spaceship {
+    /// Average distance moved per tick.
+    pub fn velocity(&self) -> f64 {
+        self.displacement as f64 / self.period as f64
+    }
+}
+
+/// Generation remembered by a `SpaceshipDetector`.
+#[derive(Copy, Clone, Debug)]
+struct Sighting {
+    tick: u64,
+    /// Lowest set address, which the generation was normalized by.
+    origin: i128,
+    /// Total bits output before this generation.
+    outputs: u64,
+}
+
+/// Detects states repeated up to translation, by remembering each generation
+/// normalized by its lowest set address, looked up by its hash.
+///
+/// As with `CycleDetector`, input clears the history, generations with equal
+/// hashes are compared in full, and only the latest `MAX_GENERATIONS` are
+/// remembered, unless given another capacity.
+#[derive(Clone, Debug)]
+pub struct SpaceshipDetector<T> {
+    /// Normalized state of each generation, with where it first occurred.
+    history: History<T, Sighting>,
+    /// Total bits output since the history was last cleared.
+    outputs: u64,
+}
+
+impl<T: State> SpaceshipDetector<T> {
+    pub fn new() -> Self {
+        SpaceshipDetector::with_capacity(MAX_GENERATIONS)
+    }
+
+    /// Detector remembering up to `capacity` generations.
+    pub fn with_capacity(capacity: usize) -> Self {
+        SpaceshipDetector {
+            history: History::with_capacity(capacity),
+            outputs: 0,
+        }
+    }
+
+    /// Forget all previous generations.
+    pub fn clear(&mut self) {
+        self.history.clear();
+        self.outputs = 0;
+    }
+
+    /// Record a generation's normalized state, its hash and its lowest set
+    /// address, and how many bits were output producing it, returning the
+    /// spaceship if it repeats an earlier generation.
+    pub fn record(
+        &mut self,
+        tick: u64,
+        hash: u64,
+        state: T,
+        origin: i128,
+        outputs: u64,
+    ) -> Option<Spaceship> {
+        self.outputs += outputs;
+
+        let sighting = Sighting {
+            tick,
+            origin,
+            outputs: self.outputs,
+        };
+        self.history.find_or_insert(hash, state, sighting)
+            .map(|earlier| Spaceship {
+                start: earlier.tick,
+                period: tick - earlier.tick,
+                displacement: origin.wrapping_sub(earlier.origin),
+                outputs: self.outputs - earlier.outputs,
+            })
+    }
+
+    /// Number of generations remembered.
+    pub fn len(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.history.is_empty()
+    }
+
+    /// Approximate number of heap bytes allocated for the history.
+    pub fn heap_bytes(&self) -> usize {
+        self.history.heap_bytes()
+    }
+}
+
+impl<T: State> Default for SpaceshipDetector<T> {
+    fn default() -> Self {
+        SpaceshipDetector::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_collision_is_not_a_spaceship() {
+        let mut detector = SpaceshipDetector::new();
+        assert_eq!(detector.record(0, 7, "a", 10, 0), None);
+        assert_eq!(detector.record(1, 7, "b", 12, 1), None);
+        assert_eq!(detector.len(), 2);
+
+        let spaceship = detector.record(4, 7, "a", 19, 2).unwrap();
+        assert_eq!(spaceship, Spaceship {
+            start: 0,
+            period: 4,
+            displacement: 9,
+            outputs: 3,
+        });
+    }
+}
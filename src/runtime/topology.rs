@@ -0,0 +1,108 @@
+/// Shape of the address space a program runs in.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug, Default)]
+pub enum Topology {
+    /// Every 128-bit address, with reads beyond either end yielding no.
+    #[default]
+    Unbounded,
+    /// A ring of the addresses `[0, len)`, around which every offset wraps,
+    /// so that address `len - 1` neighbors address `0`.
+    ///
+    /// Bits still perform I/O in ascending order of address, starting from
+    /// address `0`.
+    ///
+    /// The length must be positive and at most `i128::MAX`.
+    Ring(u128),
+}
+
+impl Topology {
+    /// Whether an address is part of the address space.
+    pub fn contains(self, address: i128) -> bool {
+        match self {
+            Topology::Unbounded => true,
+            Topology::Ring(len) => address >= 0 && (address as u128) < len,
+        }
+    }
+
+    /// The address of the address space which an address stands for.
+    pub fn wrap(self, address: i128) -> i128 {
+        match self {
+            Topology::Unbounded => address,
+            Topology::Ring(len) => address.rem_euclid(len as i128),
+        }
+    }
+
+    /// The address at an offset above another, if any.
+    pub fn add(self, address: i128, offset: i128) -> Option<i128> {
+        match self {
+            Topology::Unbounded => address.checked_add(offset),
+            Topology::Ring(len) => {
+                // both terms are below the length, so their sum fits in a u128
+                let sum = self.wrap(address) as u128 + self.wrap(offset) as u128;
+                Some((sum % len) as i128)
+            }
+        }
+    }
+
+    /// The address at an offset below another, if any.
+    pub fn sub(self, address: i128, offset: i128) -> Option<i128> {
+        match self {
+            Topology::Unbounded => address.checked_sub(offset),
+            Topology::Ring(len) => {
+                let sum = self.wrap(address) as u128 + (len - self.wrap(offset) as u128);
+                Some((sum % len) as i128)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unbounded_offsets_stop_at_the_ends() {
+        let topology = Topology::Unbounded;
+        assert_eq!(topology.add(-5, 3), Some(-2));
+        assert_eq!(topology.sub(-5, 3), Some(-8));
+        assert_eq!(topology.add(i128::MAX, 1), None);
+        assert_eq!(topology.sub(i128::MIN, 1), None);
+        assert_eq!(topology.wrap(i128::MIN), i128::MIN);
+        assert!(topology.contains(i128::MIN) && topology.contains(i128::MAX));
+    }
+
+    #[test]
+    fn ring_offsets_wrap() {
+        let topology = Topology::Ring(10);
+        assert_eq!(topology.add(9, 1), Some(0));
+        assert_eq!(topology.sub(0, 1), Some(9));
+        assert_eq!(topology.add(0, -1), Some(9));
+        assert_eq!(topology.sub(9, -1), Some(0));
+
+        // offsets beyond the length wrap as many times as they need
+        assert_eq!(topology.add(3, 25), Some(8));
+        assert_eq!(topology.sub(3, 25), Some(8));
+        assert_eq!(topology.add(3, -25), Some(8));
+        assert_eq!(topology.add(0, i128::MAX), Some(7));
+        assert_eq!(topology.sub(0, i128::MIN), Some(8));
+
+        // addresses off the ring are first wrapped onto it
+        assert_eq!(topology.add(-1, 0), Some(9));
+        assert_eq!(topology.sub(23, 0), Some(3));
+        assert_eq!(topology.wrap(-11), 9);
+        assert!(topology.contains(0) && topology.contains(9));
+        assert!(!topology.contains(-1) && !topology.contains(10));
+    }
+
+    #[test]
+    fn ring_offsets_wrap_at_extreme_lengths() {
+        let topology = Topology::Ring(i128::MAX as u128);
+        assert_eq!(topology.add(i128::MAX - 1, 1), Some(0));
+        assert_eq!(topology.add(i128::MAX - 1, i128::MAX - 1), Some(i128::MAX - 2));
+        assert_eq!(topology.sub(0, 1), Some(i128::MAX - 1));
+        assert_eq!(topology.wrap(i128::MAX), 0);
+
+        let topology = Topology::Ring(1);
+        assert_eq!(topology.add(0, i128::MIN), Some(0));
+        assert_eq!(topology.sub(0, i128::MAX), Some(0));
+    }
+}